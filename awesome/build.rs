@@ -1,11 +1,19 @@
 extern crate gcc;
 extern crate pkg_config;
+extern crate wayland_scanner;
 
 use std::{env, fs, io::Write, path::Path, process::Command};
 
+use wayland_scanner::{generate_c_code, generate_c_interfaces, Side};
+
+/// Private Way Cooler protocols, relative to the workspace root.
+const PROTOCOLS: &'static [(&'static str, &'static str)] =
+    &[("window_management", "protocols/way-cooler-window-management-unstable-v1.xml")];
+
 fn main() {
     dump_git_version();
     build_wayland_glib_interface();
+    generate_protocols();
 }
 
 /// Writes the current git hash to a file that is read by Way Cooler
@@ -58,3 +66,16 @@ fn build_wayland_glib_interface() {
     builder.file("src/wayland_glib_interface.c")
            .compile("wayland_glib_interface");
 }
+
+/// Generate the client side code for the private Way Cooler protocols.
+fn generate_protocols() {
+    let out_dir = env::var("OUT_DIR").expect("Could not find out directory!");
+    let out_dir = Path::new(&out_dir);
+    for &(name, path) in PROTOCOLS {
+        let protocol = Path::new("..").join(path);
+        generate_c_interfaces(&protocol, out_dir.join(format!("{}_interfaces.rs", name)));
+        generate_c_code(&protocol,
+                        out_dir.join(format!("{}_client_api.rs", name)),
+                        Side::Client);
+    }
+}
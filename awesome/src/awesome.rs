@@ -108,6 +108,10 @@ fn method_setup<'lua>(lua: &'lua Lua, awesome_table: &Table<'lua>) -> rlua::Resu
     awesome_table.set("get_xproperty", lua.create_function(get_xproperty)?)?;
     awesome_table.set("systray", lua.create_function(systray)?)?;
     awesome_table.set("restart", lua.create_function(restart)?)?;
+    awesome_table.set("claim_gesture",
+                       lua.create_function(::wayland_obj::claim_gesture)?)?;
+    awesome_table.set("release_gesture",
+                       lua.create_function(::wayland_obj::release_gesture)?)?;
     awesome_table.set("load_image", lua.create_function(load_image)?)?;
     awesome_table.set("pixbuf_to_surface", lua.create_function(pixbuf_to_surface)?)?;
     awesome_table.set("sync", lua.create_function(sync)?)?;
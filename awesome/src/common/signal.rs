@@ -64,6 +64,21 @@ pub fn emit_object_signal<'lua, A>(lua: &'lua Lua,
     emit_signals(lua, signals, name, args)
 }
 
+/// Evaluate the functions connected to a signal of a class, like
+/// `client.connect_signal("manage", ...)`.
+///
+/// Unlike `emit_object_signal` the class is not passed to the functions.
+pub fn emit_class_signal<'lua, A>(lua: &'lua Lua,
+                                  class: Object<'lua>,
+                                  name: String,
+                                  args: A)
+                                  -> rlua::Result<()>
+    where A: ToLuaMulti<'lua> + Clone
+{
+    let signals = class.signals()?;
+    emit_signals(lua, signals, name, args)
+}
+
 fn emit_signals<'lua, A>(_: &'lua Lua,
                          signals: Table<'lua>,
                          name: String,
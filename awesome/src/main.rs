@@ -16,6 +16,8 @@ extern crate rlua;
 extern crate xcb;
 #[macro_use]
 extern crate wayland_client;
+extern crate wayland_commons;
+extern crate wayland_sys;
 
 // TODO remove
 extern crate wlroots;
@@ -27,6 +29,7 @@ mod macros;
 mod objects;
 mod common;
 mod wayland_obj;
+mod wayland_protocols;
 
 mod awesome;
 mod keygrabber;
@@ -45,6 +48,7 @@ use xcb::{xkb, Connection};
 use wayland_client::{Display, GlobalManager};
 use wayland_client::protocol::{wl_output, wl_display::RequestsTrait};
 use wayland_client::sys::client::wl_display;
+use wayland_protocols::zway_cooler_window_management_v1::ZwayCoolerWindowManagementV1;
 
 use self::lua::{LUA, NEXT_LUA};
 
@@ -126,7 +130,10 @@ fn init_wayland() {
     let _globals = GlobalManager::new_with_cb(
         display.get_registry().unwrap(),
         global_filter!(
-            [wl_output::WlOutput, 2, wayland_obj::Output::new]
+            [wl_output::WlOutput, 2, wayland_obj::Output::new],
+            [ZwayCoolerWindowManagementV1,
+             wayland_obj::WINDOW_MANAGEMENT_VERSION,
+             wayland_obj::bind_window_management]
         ),
     );
     // TODO Remove
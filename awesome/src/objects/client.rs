@@ -1,30 +1,39 @@
 //! A client to the Wayland compositor. We control their position through tiling
 //! and other properties based on what kind of shell they are.
+//!
+//! Clients are created and updated from the views way-cooler announces
+//! through the window management protocol.
 
 use std::default::Default;
 use std::fmt::{self, Display, Formatter};
 use std::hash::{Hash, Hasher};
 
-use rlua::{self, Lua, Table, ToLua, UserData, Value};
+use rlua::{self, AnyUserData, Function, Lua, Table, ToLua, UserData, UserDataMethods, Value};
 
 use common::{class::{self, Class, ClassBuilder},
-             object::{Object, Objectable}};
+             object::{self, Object, Objectable},
+             property::Property,
+             signal};
+use wayland_obj::{View, ViewProperties};
+use LUA;
 
-#[derive(Clone, Debug, Hash)]
+/// The registry entry with the list of managed clients.
+pub const CLIENTS_HANDLE: &'static str = "__clients";
+
+/// The view itself stays in `VIEWS`, the client only knows its id and a copy
+/// of its properties.
+#[derive(Clone, Default)]
 pub struct ClientState {
-    // TODO Fill in
-    pub dummy: i32
+    /// The id of the view of the client, until it is closed.
+    view: Option<u32>,
+    /// The properties of the view as of its last update, the default once
+    /// it's closed.
+    properties: ViewProperties
 }
 
 #[derive(Clone)]
 pub struct Client<'lua>(Object<'lua>);
 
-impl Default for ClientState {
-    fn default() -> Self {
-        ClientState { dummy: 0 }
-    }
-}
-
 impl<'lua> PartialEq for Client<'lua> {
     fn eq(&self, other: &Self) -> bool {
         &*self.state().unwrap() as *const _ == &*other.state().unwrap() as *const _
@@ -35,18 +44,25 @@ impl<'lua> Eq for Client<'lua> {}
 
 impl<'lua> Hash for Client<'lua> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.state().unwrap().hash(state);
+        (&*self.state().unwrap() as *const ClientState).hash(state);
     }
 }
 
-/* This is currently unused.
- * TODO: Figure out if this will be needed later. */
 impl <'lua> Client<'lua> {
     pub fn new(lua: &'lua Lua, args: Table) -> rlua::Result<Object<'lua>> {
         let class = class::class_setup(lua, "client")?;
         Ok(Client::allocate(lua, class)?.handle_constructor_argument(args)?
                                         .build())
     }
+
+    /// The view of the client, unless it was closed.
+    fn view(&self) -> rlua::Result<Option<View>> {
+        Ok(self.state()?.view.and_then(View::from_id))
+    }
+
+    fn properties(&self) -> rlua::Result<ViewProperties> {
+        Ok(self.state()?.properties)
+    }
 }
 
 impl Display for ClientState {
@@ -61,26 +77,371 @@ impl<'lua> ToLua<'lua> for Client<'lua> {
     }
 }
 
-impl UserData for ClientState {}
+impl UserData for ClientState {
+    fn add_methods(methods: &mut UserDataMethods<Self>) {
+        object::default_add_methods(methods);
+    }
+}
 
 pub fn init(lua: &Lua) -> rlua::Result<Class> {
-    method_setup(lua, Class::builder(lua, "client", None)?)?.save_class("client")?
-                                                            .build()
+    lua.set_named_registry_value(CLIENTS_HANDLE, lua.create_table()?)?;
+    property_setup(lua, method_setup(lua, Class::builder(lua, "client", None)?)?)?
+        .save_class("client")?
+        .build()
+}
+
+/// Creates the client of a view that was just mapped, and emits `manage`.
+pub fn manage(view: View) {
+    with_lua("manage", |lua| manage_view(lua, view.id(), view.properties()));
+}
+
+/// Emits the `property::` signals of the client of a view for the
+/// properties that changed.
+pub fn update(view: &View, properties: &[&str]) {
+    with_lua("property", |lua| update_view(lua, view.id(), view.properties(), properties));
+}
+
+/// Forgets the client of a view that was closed, and emits `unmanage`.
+pub fn unmanage(view: &View) {
+    with_lua("unmanage", |lua| unmanage_view(lua, view.id()));
+}
+
+fn manage_view(lua: &Lua, view: u32, properties: ViewProperties) -> rlua::Result<()> {
+    let mut client = Client::cast(Client::new(lua, lua.create_table()?)?)?;
+    {
+        let mut state = client.get_object_mut()?;
+        state.view = Some(view);
+        state.properties = properties;
+    }
+    let clients = lua.named_registry_value::<Table>(CLIENTS_HANDLE)?;
+    clients.set(clients.len()? + 1, client.clone())?;
+    emit_signal(lua, client, "manage")
+}
+
+fn update_view(lua: &Lua,
+               view: u32,
+               properties: ViewProperties,
+               changed: &[&str])
+               -> rlua::Result<()> {
+    if let Some(mut client) = find_client(lua, view)? {
+        client.get_object_mut()?.properties = properties;
+        for property in changed {
+            emit_signal(lua, client.clone(), &format!("property::{}", property))?;
+        }
+    }
+    Ok(())
+}
+
+fn unmanage_view(lua: &Lua, view: u32) -> rlua::Result<()> {
+    let mut client = match find_client(lua, view)? {
+        Some(client) => client,
+        None => return Ok(())
+    };
+    let clients = lua.named_registry_value::<Table>(CLIENTS_HANDLE)?;
+    let remaining = lua.create_table()?;
+    for other in clients.sequence_values::<AnyUserData>() {
+        let other = other?;
+        if Client::cast(other.clone().into())? != client {
+            remaining.set(remaining.len()? + 1, other)?;
+        }
+    }
+    lua.set_named_registry_value(CLIENTS_HANDLE, remaining)?;
+    *client.get_object_mut()? = ClientState::default();
+    emit_signal(lua, client, "unmanage")
+}
+
+/// Runs `func` with the Lua state, logging the errors.
+fn with_lua<F>(what: &str, func: F)
+    where F: for<'lua> FnOnce(&'lua Lua) -> rlua::Result<()>
+{
+    let res = LUA.with(|lua| func(&*lua.borrow()));
+    if let Err(err) = res {
+        warn!("Could not emit the {} signal of a client: {:?}", what, err);
+    }
+}
+
+fn find_client<'lua>(lua: &'lua Lua, view: u32) -> rlua::Result<Option<Client<'lua>>> {
+    let clients = lua.named_registry_value::<Table>(CLIENTS_HANDLE)?;
+    for client in clients.sequence_values::<AnyUserData>() {
+        let client = Client::cast(client?.into())?;
+        if client.state()?.view == Some(view) {
+            return Ok(Some(client))
+        }
+    }
+    Ok(None)
+}
+
+/// Emits a signal on the client, then on the class with the client as the
+/// first argument, like Awesome does.
+fn emit_signal<'lua>(lua: &'lua Lua, client: Client<'lua>, name: &str) -> rlua::Result<()> {
+    signal::emit_object_signal(lua, client.0.clone(), name.into(), ())?;
+    let class: Object = lua.globals().get::<_, AnyUserData>("client")?.into();
+    signal::emit_class_signal(lua, class, name.into(), client)
 }
 
 fn method_setup<'lua>(lua: &'lua Lua,
                       builder: ClassBuilder<'lua>)
                       -> rlua::Result<ClassBuilder<'lua>> {
-    // TODO Do properly
-    use super::dummy;
-    builder.method("connect_signal".into(), lua.create_function(dummy)?)?
+    builder.method("connect_signal".into(), lua.create_function(connect_signal)?)?
            .method("__call".into(),
                lua.create_function(|lua, args: Table| Client::new(lua, args))?)?
-           .method("get".into(), lua.create_function(dummy_table)?)
+           .method("get".into(), lua.create_function(get_clients)?)
+}
+
+fn property_setup<'lua>(lua: &'lua Lua,
+                        builder: ClassBuilder<'lua>)
+                        -> rlua::Result<ClassBuilder<'lua>> {
+    builder.property(Property::new("x".into(),
+                                   None,
+                                   Some(lua.create_function(get_x)?),
+                                   Some(lua.create_function(set_x)?)))?
+           .property(Property::new("y".into(),
+                                   None,
+                                   Some(lua.create_function(get_y)?),
+                                   Some(lua.create_function(set_y)?)))?
+           .property(Property::new("width".into(),
+                                   None,
+                                   Some(lua.create_function(get_width)?),
+                                   Some(lua.create_function(set_width)?)))?
+           .property(Property::new("height".into(),
+                                   None,
+                                   Some(lua.create_function(get_height)?),
+                                   Some(lua.create_function(set_height)?)))?
+           .property(Property::new("minimized".into(),
+                                   None,
+                                   Some(lua.create_function(get_minimized)?),
+                                   Some(lua.create_function(set_minimized)?)))?
+           .property(Property::new("active".into(),
+                                   None,
+                                   Some(lua.create_function(get_active)?),
+                                   None))?
+           .property(Property::new("geometry".into(),
+                                   None,
+                                   Some(lua.create_function(|lua, _: Value| {
+                                       Ok(Value::Function(lua.create_function(geometry)?))
+                                   })?),
+                                   None))?
+           .property(Property::new("kill".into(),
+                                   None,
+                                   Some(lua.create_function(|lua, _: Value| {
+                                       Ok(Value::Function(lua.create_function(kill)?))
+                                   })?),
+                                   None))
 }
 
 impl_objectable!(Client, ClientState);
 
-fn dummy_table<'lua>(lua: &'lua Lua, _: rlua::Value) -> rlua::Result<Table<'lua>> {
-    Ok(lua.create_table()?)
+/// `client.connect_signal(name, func)`, for the signals every client emits.
+fn connect_signal(lua: &Lua, (name, func): (String, Function)) -> rlua::Result<()> {
+    let class: Object = lua.globals().get::<_, AnyUserData>("client")?.into();
+    signal::connect_signal(lua, class, name, &[func])
+}
+
+fn get_clients<'lua>(lua: &'lua Lua, _: Value) -> rlua::Result<Table<'lua>> {
+    lua.named_registry_value::<Table>(CLIENTS_HANDLE)
+}
+
+/// The geometry of the client as (x, y, width, height), zero once it's
+/// closed.
+fn client_geometry(obj: AnyUserData) -> rlua::Result<(i32, i32, i32, i32)> {
+    Ok(Client::cast(obj.into())?.properties()?.geometry)
+}
+
+/// Moves and resizes the client, leaving out what didn't change.
+fn set_client_geometry(obj: AnyUserData, geometry: (i32, i32, i32, i32)) -> rlua::Result<()> {
+    let (x, y, width, height) = geometry;
+    let (old_x, old_y, old_width, old_height) = client_geometry(obj.clone())?;
+    if let Some(view) = Client::cast(obj.into())?.view()? {
+        if (x, y) != (old_x, old_y) {
+            view.set_position(x, y);
+        }
+        if (width, height) != (old_width, old_height) {
+            view.set_size(width, height);
+        }
+    }
+    Ok(())
+}
+
+fn get_x(_: &Lua, obj: AnyUserData) -> rlua::Result<i32> {
+    Ok(client_geometry(obj)?.0)
+}
+
+fn set_x(_: &Lua, (obj, x): (AnyUserData, i32)) -> rlua::Result<()> {
+    let (_, y, width, height) = client_geometry(obj.clone())?;
+    set_client_geometry(obj, (x, y, width, height))
+}
+
+fn get_y(_: &Lua, obj: AnyUserData) -> rlua::Result<i32> {
+    Ok(client_geometry(obj)?.1)
+}
+
+fn set_y(_: &Lua, (obj, y): (AnyUserData, i32)) -> rlua::Result<()> {
+    let (x, _, width, height) = client_geometry(obj.clone())?;
+    set_client_geometry(obj, (x, y, width, height))
+}
+
+fn get_width(_: &Lua, obj: AnyUserData) -> rlua::Result<i32> {
+    Ok(client_geometry(obj)?.2)
+}
+
+fn set_width(_: &Lua, (obj, width): (AnyUserData, i32)) -> rlua::Result<()> {
+    let (x, y, _, height) = client_geometry(obj.clone())?;
+    set_client_geometry(obj, (x, y, width, height))
+}
+
+fn get_height(_: &Lua, obj: AnyUserData) -> rlua::Result<i32> {
+    Ok(client_geometry(obj)?.3)
+}
+
+fn set_height(_: &Lua, (obj, height): (AnyUserData, i32)) -> rlua::Result<()> {
+    let (x, y, width, _) = client_geometry(obj.clone())?;
+    set_client_geometry(obj, (x, y, width, height))
+}
+
+fn get_minimized(_: &Lua, obj: AnyUserData) -> rlua::Result<bool> {
+    Ok(Client::cast(obj.into())?.properties()?.minimized)
+}
+
+fn set_minimized(_: &Lua, (obj, minimized): (AnyUserData, bool)) -> rlua::Result<()> {
+    if let Some(view) = Client::cast(obj.into())?.view()? {
+        view.set_minimized(minimized);
+    }
+    Ok(())
+}
+
+fn get_active(_: &Lua, obj: AnyUserData) -> rlua::Result<bool> {
+    Ok(Client::cast(obj.into())?.properties()?.focused)
+}
+
+/// `c:geometry([geometry])` moves and resizes the client to the fields that
+/// are set in the table, and returns its geometry.
+///
+/// The new geometry only shows up once way-cooler applied it.
+fn geometry<'lua>(lua: &'lua Lua,
+                  (obj, new): (AnyUserData<'lua>, Option<Table<'lua>>))
+                  -> rlua::Result<Table<'lua>> {
+    let (x, y, width, height) = client_geometry(obj.clone())?;
+    if let Some(new) = new {
+        let geometry = (new.get::<_, Option<i32>>("x")?.unwrap_or(x),
+                        new.get::<_, Option<i32>>("y")?.unwrap_or(y),
+                        new.get::<_, Option<i32>>("width")?.unwrap_or(width),
+                        new.get::<_, Option<i32>>("height")?.unwrap_or(height));
+        set_client_geometry(obj, geometry)?;
+    }
+    let table = lua.create_table()?;
+    table.set("x", x)?;
+    table.set("y", y)?;
+    table.set("width", width)?;
+    table.set("height", height)?;
+    Ok(table)
+}
+
+/// `c:kill()` asks the client to close.
+fn kill(_: &Lua, obj: AnyUserData) -> rlua::Result<()> {
+    if let Some(view) = Client::cast(obj.into())?.view()? {
+        view.close();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::client;
+    use rlua::Lua;
+    use wayland_obj::ViewProperties;
+
+    const PROPERTIES: ViewProperties = ViewProperties { geometry: (10, 20, 640, 480),
+                                                        focused: true,
+                                                        minimized: false };
+
+    #[test]
+    fn client_properties_default() {
+        let lua = Lua::new();
+        client::init(&lua).unwrap();
+        lua.eval::<()>(r#"
+local c = client{}
+assert(c.x == 0 and c.y == 0 and c.width == 0 and c.height == 0)
+assert(c.active == false)
+assert(c.minimized == false)
+"#,
+                       None)
+           .unwrap()
+    }
+
+    #[test]
+    fn client_manage() {
+        let lua = Lua::new();
+        client::init(&lua).unwrap();
+        lua.eval::<()>(r#"
+managed = nil
+client.connect_signal("manage", function(c) managed = c end)
+"#,
+                       None)
+           .unwrap();
+        client::manage_view(&lua, 3, PROPERTIES).unwrap();
+        lua.eval::<()>(r#"
+assert(managed ~= nil)
+assert(#client.get() == 1 and client.get()[1] == managed)
+assert(managed.x == 10 and managed.y == 20)
+assert(managed.width == 640 and managed.height == 480)
+local geometry = managed:geometry()
+assert(geometry.x == 10 and geometry.width == 640)
+assert(managed.active == true)
+assert(managed.minimized == false)
+"#,
+                       None)
+           .unwrap()
+    }
+
+    #[test]
+    fn client_update() {
+        let lua = Lua::new();
+        client::init(&lua).unwrap();
+        client::manage_view(&lua, 3, PROPERTIES).unwrap();
+        lua.eval::<()>(r#"
+called = 0
+client.connect_signal("property::minimized", function(c)
+    assert(c.minimized == true)
+    called = called + 1
+end)
+"#,
+                       None)
+           .unwrap();
+        let minimized = ViewProperties { minimized: true, ..PROPERTIES };
+        // Views that don't have a client are ignored.
+        client::update_view(&lua, 4, minimized, &["minimized"]).unwrap();
+        lua.eval::<()>("assert(called == 0)", None).unwrap();
+        client::update_view(&lua, 3, minimized, &["minimized"]).unwrap();
+        lua.eval::<()>(r#"
+assert(called == 1)
+assert(client.get()[1].minimized == true)
+"#,
+                       None)
+           .unwrap()
+    }
+
+    #[test]
+    fn client_unmanage() {
+        let lua = Lua::new();
+        client::init(&lua).unwrap();
+        client::manage_view(&lua, 3, PROPERTIES).unwrap();
+        client::manage_view(&lua, 4, PROPERTIES).unwrap();
+        lua.eval::<()>(r#"
+first = client.get()[1]
+unmanaged = nil
+client.connect_signal("unmanage", function(c) unmanaged = c end)
+"#,
+                       None)
+           .unwrap();
+        client::unmanage_view(&lua, 3).unwrap();
+        // Only once.
+        client::unmanage_view(&lua, 3).unwrap();
+        lua.eval::<()>(r#"
+assert(unmanaged == first)
+assert(#client.get() == 1 and client.get()[1] ~= first)
+assert(first.width == 0 and first.active == false)
+"#,
+                       None)
+           .unwrap()
+    }
 }
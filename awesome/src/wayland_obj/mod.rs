@@ -1,6 +1,8 @@
 //! Wrappers around Wayland objects
 
 mod output;
+mod window_management;
 
 pub use self::output::Output;
-
+pub use self::window_management::{bind_window_management, claim_gesture, release_gesture, View,
+                                  ViewProperties, VIEWS, WINDOW_MANAGEMENT_VERSION};
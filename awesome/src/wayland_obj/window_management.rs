@@ -0,0 +1,325 @@
+//! Wrapper around the private window management protocol of way-cooler.
+
+use std::cell::RefCell;
+use std::ptr;
+
+use rlua::{self, Lua, Value};
+use wayland_client::{NewProxy, Proxy};
+
+use wayland_protocols::zway_cooler_view_v1::{RequestsTrait as ViewRequests, ZwayCoolerViewV1};
+use wayland_protocols::zway_cooler_window_management_v1::{RequestsTrait as ManagerRequests,
+                                                          ZwayCoolerWindowManagementV1};
+
+use ::LUA;
+use common::signal;
+use objects::client;
+
+/// The version of the window management protocol we speak.
+pub const WINDOW_MANAGEMENT_VERSION: u32 = 4;
+
+/// The names of the gestures in Lua, by their value in the protocol.
+const GESTURES: &'static [&'static str] = &["swipe", "pinch"];
+
+thread_local! {
+    /// The window management global, once it is bound.
+    static MANAGER: RefCell<Option<Proxy<ZwayCoolerWindowManagementV1>>> = RefCell::new(None);
+    /// All the views that way-cooler has told us about.
+    pub static VIEWS: RefCell<Vec<View>> = RefCell::new(Vec::new());
+    /// The axis events received since the last axis frame.
+    static PENDING_AXIS: RefCell<Vec<Axis>> = RefCell::new(Vec::new());
+}
+
+/// A scroll of the pointer, with the values of the `wl_pointer` events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Axis {
+    time: u32,
+    source: u32,
+    orientation: u32,
+    value: f64,
+    discrete: i32
+}
+
+/// Wrapper around ZwayCoolerViewV1.
+#[derive(Clone, Eq, PartialEq)]
+pub struct View {
+    proxy: Proxy<ZwayCoolerViewV1>
+}
+
+/// The cached state for the ZwayCoolerViewV1.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+struct ViewState {
+    /// State that is applied on the next done event.
+    pending: ViewProperties,
+    current: ViewProperties,
+    /// Whether the client of the view was created, on the first done event.
+    managed: bool
+}
+
+/// The state of a view as of its last done event.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct ViewProperties {
+    /// The position and size of the view, as (x, y, width, height).
+    pub geometry: (i32, i32, i32, i32),
+    /// Whether the view has keyboard focus.
+    pub focused: bool,
+    pub minimized: bool
+}
+
+impl ViewProperties {
+    /// The names of the client properties that differ between the two.
+    fn changed(&self, other: &ViewProperties) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        let (x, y, width, height) = self.geometry;
+        let (new_x, new_y, new_width, new_height) = other.geometry;
+        if (x, y) != (new_x, new_y) {
+            changed.push("position");
+        }
+        if (width, height) != (new_width, new_height) {
+            changed.push("size");
+        }
+        if self.geometry != other.geometry {
+            changed.push("geometry");
+        }
+        if self.focused != other.focused {
+            changed.push("active");
+        }
+        if self.minimized != other.minimized {
+            changed.push("minimized");
+        }
+        changed
+    }
+}
+
+impl <'this> Into<&'this Proxy<ZwayCoolerViewV1>> for &'this View {
+    fn into(self) -> &'this Proxy<ZwayCoolerViewV1> {
+        &self.proxy
+    }
+}
+
+/// Binds the window management global of way-cooler.
+///
+/// Every view announced through it is added to `VIEWS`, and gets a client once
+/// its first state is done.
+pub fn bind_window_management(new_proxy: Result<NewProxy<ZwayCoolerWindowManagementV1>, u32>,
+                              _: ()) {
+    let new_proxy = match new_proxy {
+        Ok(new_proxy) => new_proxy,
+        Err(version) => {
+            error!("way-cooler only supports window management version {}, we need {}",
+                   version, WINDOW_MANAGEMENT_VERSION);
+            return
+        }
+    };
+    let manager = new_proxy.implement(|event, _| {
+        use wayland_protocols::zway_cooler_window_management_v1::Event;
+        match event {
+            Event::View { id } => View::new(id),
+            Event::Axis { time, source, orientation, value, discrete } => {
+                let axis = Axis { time, source, orientation, value, discrete };
+                PENDING_AXIS.with(|pending| pending.borrow_mut().push(axis));
+            },
+            Event::AxisFrame => {
+                let axes = PENDING_AXIS.with(|pending| pending.replace(Vec::new()));
+                if let Err(err) = LUA.with(|lua| emit_axis(&*lua.borrow(), &axes)) {
+                    warn!("Could not emit pointer::axis: {:?}", err);
+                }
+            },
+            Event::GestureBegin { time, gesture, fingers } => {
+                emit_gesture("gesture::begin", time, gesture, |event| {
+                    event.set("fingers", fingers)
+                });
+            },
+            Event::GestureUpdate { time, gesture, dx, dy, scale, rotation } => {
+                emit_gesture("gesture::update", time, gesture, |event| {
+                    event.set("dx", dx)?;
+                    event.set("dy", dy)?;
+                    event.set("scale", scale)?;
+                    event.set("rotation", rotation)
+                });
+            },
+            Event::GestureEnd { time, gesture, cancelled } => {
+                emit_gesture("gesture::end", time, gesture, |event| {
+                    event.set("cancelled", cancelled != 0)
+                });
+            }
+        }
+    });
+    MANAGER.with(|global| *global.borrow_mut() = Some(manager));
+}
+
+/// Binds the gestures of a type (`"swipe"` or `"pinch"`) with a number of
+/// fingers, so they emit the `gesture::` signals instead of going to the
+/// client under the pointer.
+pub fn claim_gesture(_: &Lua, (gesture, fingers): (String, u32)) -> rlua::Result<()> {
+    let gesture = gesture_value(&gesture)?;
+    MANAGER.with(|manager| {
+                     if let Some(ref manager) = *manager.borrow() {
+                         manager.claim_gesture(gesture, fingers);
+                     }
+                 });
+    Ok(())
+}
+
+/// Undoes `claim_gesture`, the gestures go to the client under the pointer
+/// again.
+pub fn release_gesture(_: &Lua, (gesture, fingers): (String, u32)) -> rlua::Result<()> {
+    let gesture = gesture_value(&gesture)?;
+    MANAGER.with(|manager| {
+                     if let Some(ref manager) = *manager.borrow() {
+                         manager.release_gesture(gesture, fingers);
+                     }
+                 });
+    Ok(())
+}
+
+fn gesture_value(name: &str) -> rlua::Result<u32> {
+    GESTURES.iter()
+            .position(|&gesture| gesture == name)
+            .map(|value| value as u32)
+            .ok_or_else(|| rlua::Error::RuntimeError(format!("{} is not a gesture", name)))
+}
+
+/// Emits a global signal for a touchpad gesture, with a table that has the
+/// time and the type of the gesture (`"swipe"` or `"pinch"`), and the fields
+/// set by `fill`.
+///
+/// This is how bindings like swiping with three fingers to switch tags work.
+fn emit_gesture<F>(name: &str, time: u32, gesture: u32, fill: F)
+    where F: for<'lua> FnOnce(&rlua::Table<'lua>) -> rlua::Result<()>
+{
+    let res = LUA.with(|lua| {
+        let lua = lua.borrow();
+        let event = lua.create_table()?;
+        event.set("time", time)?;
+        event.set("type", GESTURES.get(gesture as usize).cloned().unwrap_or("unknown"))?;
+        fill(&event)?;
+        signal::global_emit_signal(&*lua, (name.into(), Value::Table(event)))
+    });
+    if let Err(err) = res {
+        warn!("Could not emit {}: {:?}", name, err);
+    }
+}
+
+/// Emits the global `pointer::axis` signal with the axis events of a frame,
+/// as a list of tables with the time, source, orientation, value and
+/// discrete steps of each one.
+///
+/// This is how bindings like scrolling on the wallpaper to switch tags work.
+fn emit_axis(lua: &Lua, axes: &[Axis]) -> rlua::Result<()> {
+    let events = lua.create_table()?;
+    for (index, axis) in axes.iter().enumerate() {
+        let event = lua.create_table()?;
+        event.set("time", axis.time)?;
+        event.set("source", axis.source)?;
+        event.set("orientation", axis.orientation)?;
+        event.set("value", axis.value)?;
+        event.set("discrete", axis.discrete)?;
+        events.set(index + 1, event)?;
+    }
+    signal::global_emit_signal(lua, ("pointer::axis".into(), Value::Table(events)))
+}
+
+impl View {
+    fn new(new_proxy: NewProxy<ZwayCoolerViewV1>) {
+        let state = Box::new(ViewState::default());
+        let proxy = new_proxy.implement(move |event, mut proxy| {
+            use wayland_protocols::zway_cooler_view_v1::Event;
+            match event {
+                Event::Geometry { x, y, width, height } => {
+                    unwrap_state_mut(&mut proxy).pending.geometry = (x, y, width, height);
+                },
+                Event::Focused { focused } => {
+                    unwrap_state_mut(&mut proxy).pending.focused = focused != 0;
+                },
+                Event::Minimized { minimized } => {
+                    unwrap_state_mut(&mut proxy).pending.minimized = minimized != 0;
+                },
+                Event::Done => {
+                    let (managed, changed) = {
+                        let state = unwrap_state_mut(&mut proxy);
+                        let changed = state.current.changed(&state.pending);
+                        let managed = state.managed;
+                        state.current = state.pending;
+                        state.managed = true;
+                        (managed, changed)
+                    };
+                    let view = View { proxy: proxy.clone() };
+                    if managed {
+                        client::update(&view, &changed);
+                    } else {
+                        client::manage(view);
+                    }
+                },
+                Event::Closed => {
+                    VIEWS.with(|views| views.borrow_mut().retain(|view| view.proxy != proxy));
+                    client::unmanage(&View { proxy: proxy.clone() });
+                    unsafe {
+                        let user_data = proxy.get_user_data() as *mut ViewState;
+                        proxy.set_user_data(ptr::null_mut());
+                        Box::from_raw(user_data);
+                    }
+                    proxy.destroy();
+                }
+            }
+        });
+        proxy.set_user_data(Box::into_raw(state) as _);
+        let view = View { proxy };
+        VIEWS.with(|views| views.borrow_mut().push(view));
+    }
+
+    /// The view with the id of its protocol object, unless it was closed.
+    pub fn from_id(id: u32) -> Option<View> {
+        VIEWS.with(|views| views.borrow().iter().find(|view| view.id() == id).cloned())
+    }
+
+    /// The id of the protocol object, which identifies the view as long as
+    /// it isn't closed.
+    pub fn id(&self) -> u32 {
+        self.proxy.id()
+    }
+
+    pub fn properties(&self) -> ViewProperties {
+        unwrap_state(self).current
+    }
+
+    pub fn set_position(&self, x: i32, y: i32) {
+        self.proxy.set_position(x, y);
+    }
+
+    pub fn set_size(&self, width: i32, height: i32) {
+        self.proxy.set_size(width, height);
+    }
+
+    pub fn focus(&self) {
+        self.proxy.focus();
+    }
+
+    pub fn close(&self) {
+        self.proxy.close();
+    }
+
+    pub fn set_minimized(&self, minimized: bool) {
+        self.proxy.set_minimized(minimized as u32);
+    }
+}
+
+fn unwrap_state_mut<'this, I: Into<&'this mut Proxy<ZwayCoolerViewV1>>>(proxy: I)
+                                                                        -> &'this mut ViewState {
+    unsafe {
+        let user_data = proxy.into().get_user_data() as *mut ViewState;
+        if user_data.is_null() {
+            panic!("User data has not been set yet");
+        }
+        &mut *user_data
+    }
+}
+
+fn unwrap_state<'this, I: Into<&'this Proxy<ZwayCoolerViewV1>>>(proxy: I) -> &'this ViewState {
+    unsafe {
+        let user_data = proxy.into().get_user_data() as *const ViewState;
+        if user_data.is_null() {
+            panic!("User data has not been set yet");
+        }
+        &*user_data
+    }
+}
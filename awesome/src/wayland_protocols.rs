@@ -0,0 +1,23 @@
+//! Client side code of the private Way Cooler protocols.
+//!
+//! The code is generated by the build script from the XML files in the
+//! `protocols` directory at the root of the repository.
+
+pub use self::generated::client::*;
+
+mod generated {
+    #![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+    #![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+
+    pub mod c_interfaces {
+        pub(crate) use wayland_sys::common::*;
+        include!(concat!(env!("OUT_DIR"), "/window_management_interfaces.rs"));
+    }
+
+    pub mod client {
+        pub(crate) use wayland_client::{NewProxy, Proxy};
+        pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup};
+        pub(crate) use wayland_sys as sys;
+        include!(concat!(env!("OUT_DIR"), "/window_management_client_api.rs"));
+    }
+}
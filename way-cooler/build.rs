@@ -1,7 +1,23 @@
+extern crate wayland_scanner;
+
 use std::{env, fs, io::Write, path::Path, process::Command};
 
+use wayland_scanner::generate_interfaces;
+
+/// Protocols implemented by Way Cooler instead of wlroots, relative to the
+/// workspace root.
+const PROTOCOLS: &'static [(&'static str, &'static str)] =
+    &[("window_management", "protocols/way-cooler-window-management-unstable-v1.xml"),
+      ("xdg_decoration", "protocols/xdg-decoration-unstable-v1.xml"),
+      ("output_management", "protocols/wlr-output-management-unstable-v1.xml"),
+      ("tablet", "protocols/tablet-unstable-v2.xml"),
+      ("pointer_gestures", "protocols/pointer-gestures-unstable-v1.xml"),
+      ("pointer_constraints", "protocols/pointer-constraints-unstable-v1.xml"),
+      ("relative_pointer", "protocols/relative-pointer-unstable-v1.xml")];
+
 fn main() {
     dump_git_version();
+    generate_protocols();
 }
 
 /// Writes the current git hash to a file that is read by Way Cooler
@@ -41,3 +57,15 @@ fn in_release_commit() -> bool {
                                     .unwrap();
     result.status.success()
 }
+
+/// Generates the C interfaces for the protocols.
+///
+/// The requests and events are dispatched by hand in `src/protocols`.
+fn generate_protocols() {
+    let out_dir = env::var("OUT_DIR").expect("Could not find out directory!");
+    let out_dir = Path::new(&out_dir);
+    for &(name, path) in PROTOCOLS {
+        let protocol = Path::new("..").join(path);
+        generate_interfaces(&protocol, out_dir.join(format!("{}_interfaces.rs", name)));
+    }
+}
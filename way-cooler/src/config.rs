@@ -0,0 +1,682 @@
+//! Configuration of the compositor itself.
+//!
+//! awesome configures everything about window management, this is for the
+//! things the compositor needs to know before awesome is even running, like
+//! the keyboard layout.
+//!
+//! The file lives at `$XDG_CONFIG_HOME/way-cooler/config` and is made of
+//! sections of `key = value` pairs. Sections can be labeled to only apply to
+//! a single device:
+//!
+//! ```ini
+//! [keyboard]
+//! layout = de
+//! options = caps:escape
+//!
+//! [keyboard "Kinesis Advantage2 Keyboard"]
+//! layout = us
+//! variant = dvorak
+//!
+//! [bindings]
+//! Logo+Shift+Escape = terminate
+//! Ctrl+Alt+BackSpace = none
+//!
+//! [pointer]
+//! modifier = Alt
+//!
+//! [cursor]
+//! theme = Adwaita
+//! size = 32
+//!
+//! [touch "ELAN Touchscreen"]
+//! output = eDP-1
+//!
+//! [decorations]
+//! mode = server
+//! border_width = 2
+//! focused_color = #4c7899
+//! unfocused_color = #333333
+//!
+//! [output "eDP-1"]
+//! mode = 1920x1080@60
+//! position = 1920,0
+//! scale = 1.5
+//! transform = 90
+//! ```
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use wlroots::xkbcommon::xkb::{self, keysyms};
+use wlroots::{KeyboardModifier, Origin, OutputTransform};
+
+/// Something the compositor does itself when a binding is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingAction {
+    Terminate,
+    ReloadConfig,
+    SwitchVt(u32)
+}
+
+/// A mode of an output. The refresh rate is in Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputModeConfig {
+    pub width: i32,
+    pub height: i32,
+    /// If not set, the fastest refresh rate is used.
+    pub refresh: Option<f32>
+}
+
+/// How an output is set up. Anything that is not set is chosen
+/// automatically.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputConfig {
+    pub enabled: bool,
+    pub mode: Option<OutputModeConfig>,
+    /// Where the output is in the layout.
+    pub position: Option<Origin>,
+    pub scale: Option<f32>,
+    pub transform: Option<OutputTransform>
+}
+
+/// Who draws the decorations of a view, like its title bar and borders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationMode {
+    ClientSide,
+    ServerSide
+}
+
+/// A chord that triggers a compositor action.
+///
+/// The key events of a binding are never sent to clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub modifiers: KeyboardModifier,
+    pub keysym: u32,
+    pub action: BindingAction
+}
+
+/// The names used to compile an XKB keymap.
+///
+/// Anything that is left out is taken from the `XKB_DEFAULT_*` environment
+/// variables by xkbcommon, and from the system defaults after that.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KeymapConfig {
+    pub rules: Option<String>,
+    pub model: Option<String>,
+    pub layout: Option<String>,
+    pub variant: Option<String>,
+    pub options: Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// The keymap of every keyboard without an override.
+    pub keymap: KeymapConfig,
+    /// Keymap overrides, by input device name.
+    pub device_keymaps: HashMap<String, KeymapConfig>,
+    pub bindings: Vec<Binding>,
+    /// The modifier that has to be held to move and resize views with the
+    /// pointer. Empty if that is disabled.
+    pub pointer_modifier: KeyboardModifier,
+    /// The cursor theme, instead of the one in `XCURSOR_THEME`.
+    pub cursor_theme: Option<String>,
+    /// The size of the cursor, instead of the one in `XCURSOR_SIZE`.
+    pub cursor_size: Option<u32>,
+    /// The output of every touch device without an override.
+    pub touch_output: Option<String>,
+    /// Touch output overrides, by input device name.
+    pub device_touch_outputs: HashMap<String, String>,
+    /// The decoration mode of clients that don't have a preference.
+    pub decoration_mode: DecorationMode,
+    /// How wide the borders of server side decorated views are.
+    pub border_width: i32,
+    pub focused_border_color: [f32; 4],
+    pub unfocused_border_color: [f32; 4],
+    /// Output configurations, by connector name (e.g `HDMI-A-1`) or by
+    /// make and model (e.g `Dell Inc. DELL U2415`).
+    pub outputs: HashMap<String, OutputConfig>
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig { enabled: true,
+                       mode: None,
+                       position: None,
+                       scale: None,
+                       transform: None }
+    }
+}
+
+impl OutputConfig {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "enabled" => {
+                self.enabled = match value {
+                    "true" | "yes" => true,
+                    "false" | "no" => false,
+                    _ => return Err(format!("Invalid boolean \"{}\"", value))
+                }
+            }
+            "mode" => self.mode = Some(parse_mode(value)?),
+            "position" => {
+                let invalid = || format!("Invalid position \"{}\", expected X,Y", value);
+                let mut coords = value.splitn(2, ',').map(|coord| coord.trim().parse());
+                match (coords.next(), coords.next()) {
+                    (Some(Ok(x)), Some(Ok(y))) => self.position = Some(Origin::new(x, y)),
+                    _ => return Err(invalid())
+                }
+            }
+            "scale" => {
+                self.scale = match value.parse() {
+                    Ok(scale) if scale > 0.0 => Some(scale),
+                    _ => return Err(format!("Invalid scale \"{}\"", value))
+                }
+            }
+            "transform" => {
+                self.transform = Some(match value {
+                    "normal" | "0" => OutputTransform::WL_OUTPUT_TRANSFORM_NORMAL,
+                    "90" => OutputTransform::WL_OUTPUT_TRANSFORM_90,
+                    "180" => OutputTransform::WL_OUTPUT_TRANSFORM_180,
+                    "270" => OutputTransform::WL_OUTPUT_TRANSFORM_270,
+                    "flipped" => OutputTransform::WL_OUTPUT_TRANSFORM_FLIPPED,
+                    "flipped-90" => OutputTransform::WL_OUTPUT_TRANSFORM_FLIPPED_90,
+                    "flipped-180" => OutputTransform::WL_OUTPUT_TRANSFORM_FLIPPED_180,
+                    "flipped-270" => OutputTransform::WL_OUTPUT_TRANSFORM_FLIPPED_270,
+                    _ => return Err(format!("Invalid transform \"{}\"", value))
+                })
+            }
+            _ => return Err(format!("Unknown output setting \"{}\"", key))
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        let ctrl_alt = KeyboardModifier::WLR_MODIFIER_CTRL | KeyboardModifier::WLR_MODIFIER_ALT;
+        let mut bindings = vec![Binding { modifiers: ctrl_alt,
+                                          keysym: keysyms::KEY_BackSpace,
+                                          action: BindingAction::Terminate }];
+        // The kernel doesn't switch VTs for us while we own the session.
+        for vt in 1..13 {
+            bindings.push(Binding { modifiers: ctrl_alt,
+                                    keysym: keysyms::KEY_XF86Switch_VT_1 + vt - 1,
+                                    action: BindingAction::SwitchVt(vt) });
+        }
+        Config { keymap: KeymapConfig::default(),
+                 device_keymaps: HashMap::new(),
+                 bindings,
+                 pointer_modifier: KeyboardModifier::WLR_MODIFIER_LOGO,
+                 cursor_theme: None,
+                 cursor_size: None,
+                 touch_output: None,
+                 device_touch_outputs: HashMap::new(),
+                 decoration_mode: DecorationMode::ServerSide,
+                 border_width: 2,
+                 focused_border_color: [0.3, 0.47, 0.6, 1.0],
+                 unfocused_border_color: [0.2, 0.2, 0.2, 1.0],
+                 outputs: HashMap::new() }
+    }
+}
+
+/// A `[name "label"]` section of the config file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Section {
+    name: String,
+    label: Option<String>,
+    entries: Vec<(String, String)>
+}
+
+impl KeymapConfig {
+    /// Overrides the names that are set in `other`.
+    fn merge(&self, other: &KeymapConfig) -> KeymapConfig {
+        KeymapConfig { rules: other.rules.clone().or_else(|| self.rules.clone()),
+                       model: other.model.clone().or_else(|| self.model.clone()),
+                       layout: other.layout.clone().or_else(|| self.layout.clone()),
+                       variant: other.variant.clone().or_else(|| self.variant.clone()),
+                       options: other.options.clone().or_else(|| self.options.clone()) }
+    }
+
+    fn set(&mut self, key: &str, value: String) -> Result<(), String> {
+        match key {
+            "rules" => self.rules = Some(value),
+            "model" => self.model = Some(value),
+            "layout" => self.layout = Some(value),
+            "variant" => self.variant = Some(value),
+            "options" => self.options = Some(value),
+            _ => return Err(format!("Unknown keyboard setting \"{}\"", key))
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to the defaults if it doesn't
+    /// exist or is invalid.
+    pub fn load() -> Config {
+        let path = match config_path() {
+            Some(path) => path,
+            None => {
+                warn!("Neither XDG_CONFIG_HOME nor HOME are set, using the default config");
+                return Config::default()
+            }
+        };
+        let mut contents = String::new();
+        let read = File::open(&path).and_then(|mut file| file.read_to_string(&mut contents));
+        match read {
+            Ok(_) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                info!("No config at {}, using the defaults", path.display());
+                return Config::default()
+            }
+            Err(err) => {
+                error!("Could not read {}: {}", path.display(), err);
+                return Config::default()
+            }
+        }
+        match Config::parse(&contents) {
+            Ok(config) => {
+                info!("Loaded config from {}", path.display());
+                config
+            }
+            Err(err) => {
+                error!("Invalid config at {}: {}", path.display(), err);
+                Config::default()
+            }
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for section in parse_sections(contents)? {
+            match (section.name.as_str(), section.label) {
+                ("keyboard", label) => {
+                    let keymap = match label {
+                        Some(device) => config.device_keymaps.entry(device).or_default(),
+                        None => &mut config.keymap
+                    };
+                    for (key, value) in section.entries {
+                        keymap.set(&key, value)?;
+                    }
+                }
+                ("bindings", _) => {
+                    for (chord, action) in section.entries {
+                        config.bind(&chord, &action)?;
+                    }
+                }
+                ("pointer", _) => {
+                    for (key, value) in section.entries {
+                        match key.as_str() {
+                            "modifier" if value == "none" => {
+                                config.pointer_modifier = KeyboardModifier::empty()
+                            }
+                            "modifier" => {
+                                config.pointer_modifier = parse_modifier(&value)?
+                            }
+                            _ => return Err(format!("Unknown pointer setting \"{}\"", key))
+                        }
+                    }
+                }
+                ("cursor", _) => {
+                    for (key, value) in section.entries {
+                        match key.as_str() {
+                            "theme" => config.cursor_theme = Some(value),
+                            "size" => {
+                                config.cursor_size = match value.parse() {
+                                    Ok(size) if size > 0 => Some(size),
+                                    _ => return Err(format!("Invalid cursor size \"{}\"", value))
+                                }
+                            }
+                            _ => return Err(format!("Unknown cursor setting \"{}\"", key))
+                        }
+                    }
+                }
+                ("touch", label) => {
+                    for (key, value) in section.entries {
+                        match key.as_str() {
+                            "output" => match label {
+                                Some(ref device) => {
+                                    config.device_touch_outputs.insert(device.clone(), value);
+                                }
+                                None => config.touch_output = Some(value)
+                            },
+                            _ => return Err(format!("Unknown touch setting \"{}\"", key))
+                        }
+                    }
+                }
+                ("decorations", _) => {
+                    for (key, value) in section.entries {
+                        config.set_decoration(&key, &value)?;
+                    }
+                }
+                ("output", Some(output)) => {
+                    let output_config = config.outputs.entry(output).or_default();
+                    for (key, value) in section.entries {
+                        output_config.set(&key, &value)?;
+                    }
+                }
+                ("output", None) => return Err("Output sections need a name".into()),
+                (name, _) => warn!("Ignoring unknown config section [{}]", name)
+            }
+        }
+        Ok(config)
+    }
+
+    /// The action bound to a key, if there is one.
+    pub fn binding_action(&self,
+                          modifiers: KeyboardModifier,
+                          keysym: u32)
+                          -> Option<BindingAction> {
+        // Locks like Caps Lock and Num Lock don't stop a binding from
+        // triggering.
+        let modifiers = modifiers &
+                        (KeyboardModifier::WLR_MODIFIER_SHIFT |
+                         KeyboardModifier::WLR_MODIFIER_CTRL |
+                         KeyboardModifier::WLR_MODIFIER_ALT |
+                         KeyboardModifier::WLR_MODIFIER_LOGO);
+        self.bindings
+            .iter()
+            .find(|binding| binding.modifiers == modifiers && binding.keysym == keysym)
+            .map(|binding| binding.action)
+    }
+
+    /// Binds a chord like `Ctrl+Alt+BackSpace` to an action, replacing any
+    /// existing binding of that chord. `none` removes the binding.
+    fn bind(&mut self, chord: &str, action: &str) -> Result<(), String> {
+        let (modifiers, keysym) = parse_chord(chord)?;
+        self.bindings
+            .retain(|binding| binding.modifiers != modifiers || binding.keysym != keysym);
+        let mut words = action.split_whitespace();
+        let action = match (words.next(), words.next()) {
+            (Some("none"), None) => return Ok(()),
+            (Some("terminate"), None) => BindingAction::Terminate,
+            (Some("reload"), None) => BindingAction::ReloadConfig,
+            (Some("switch-vt"), Some(vt)) => {
+                let vt = vt.parse().map_err(|_| format!("Invalid VT number \"{}\"", vt))?;
+                BindingAction::SwitchVt(vt)
+            }
+            _ => return Err(format!("Unknown action \"{}\" for {}", action, chord))
+        };
+        self.bindings.push(Binding { modifiers,
+                                     keysym,
+                                     action });
+        Ok(())
+    }
+
+    fn set_decoration(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "mode" => {
+                self.decoration_mode = match value {
+                    "client" => DecorationMode::ClientSide,
+                    "server" => DecorationMode::ServerSide,
+                    _ => return Err(format!("Unknown decoration mode \"{}\"", value))
+                }
+            }
+            "border_width" => {
+                self.border_width = match value.parse() {
+                    Ok(width) if width >= 0 => width,
+                    _ => return Err(format!("Invalid border width \"{}\"", value))
+                }
+            }
+            "focused_color" => self.focused_border_color = parse_color(value)?,
+            "unfocused_color" => self.unfocused_border_color = parse_color(value)?,
+            _ => return Err(format!("Unknown decoration setting \"{}\"", key))
+        }
+        Ok(())
+    }
+
+    /// The cursor theme from the config, or else from the environment.
+    pub fn cursor_theme(&self) -> String {
+        self.cursor_theme
+            .clone()
+            .or_else(|| env::var("XCURSOR_THEME").ok())
+            .unwrap_or_else(|| "default".into())
+    }
+
+    /// The cursor size from the config, or else from the environment.
+    pub fn cursor_size(&self) -> u32 {
+        self.cursor_size
+            .or_else(|| env::var("XCURSOR_SIZE").ok().and_then(|size| size.parse().ok()))
+            .unwrap_or(24)
+    }
+
+    /// The configuration of an output, preferring the one for its connector
+    /// over the one for its make and model.
+    pub fn output_config(&self, name: &str, make: &str, model: &str) -> OutputConfig {
+        self.outputs
+            .get(name)
+            .or_else(|| self.outputs.get(&format!("{} {}", make, model)))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The name of the output a touch device is mapped to, if it isn't
+    /// spread over the whole layout.
+    pub fn touch_output_for(&self, device_name: &str) -> Option<&str> {
+        self.device_touch_outputs
+            .get(device_name)
+            .or(self.touch_output.as_ref())
+            .map(String::as_str)
+    }
+
+    /// The keymap for a keyboard, with the overrides for that device applied.
+    pub fn keymap_for(&self, device_name: &str) -> KeymapConfig {
+        match self.device_keymaps.get(device_name) {
+            Some(device_keymap) => self.keymap.merge(device_keymap),
+            None => self.keymap.clone()
+        }
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let config_home = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(env::var("HOME").ok()?).join(".config")
+    };
+    Some(config_home.join("way-cooler").join("config"))
+}
+
+/// Parses a mode of the form `WIDTHxHEIGHT` or `WIDTHxHEIGHT@REFRESH`.
+fn parse_mode(mode: &str) -> Result<OutputModeConfig, String> {
+    let invalid = || format!("Invalid mode \"{}\", expected WIDTHxHEIGHT[@REFRESH]", mode);
+    let mut parts = mode.splitn(2, '@');
+    let size = parts.next().unwrap_or("");
+    let refresh = match parts.next() {
+        Some(refresh) => {
+            Some(refresh.trim_right_matches("Hz").parse::<f32>().map_err(|_| invalid())?)
+        }
+        None => None
+    };
+    let mut size = size.splitn(2, 'x').map(|size| size.parse::<i32>());
+    match (size.next(), size.next()) {
+        (Some(Ok(width)), Some(Ok(height))) if width > 0 && height > 0 => {
+            Ok(OutputModeConfig { width,
+                                  height,
+                                  refresh })
+        }
+        _ => Err(invalid())
+    }
+}
+
+/// Parses a color of the form `#rrggbb` or `#rrggbbaa`.
+fn parse_color(color: &str) -> Result<[f32; 4], String> {
+    let invalid = || format!("Invalid color \"{}\", expected #rrggbb or #rrggbbaa", color);
+    if !color.starts_with('#') || (color.len() != 7 && color.len() != 9) {
+        return Err(invalid())
+    }
+    let mut rgba = [1.0; 4];
+    for (index, channel) in rgba.iter_mut().enumerate().take((color.len() - 1) / 2) {
+        let hex = color.get(1 + index * 2..3 + index * 2).ok_or_else(invalid)?;
+        *channel = u8::from_str_radix(hex, 16).map_err(|_| invalid())? as f32 / 255.0;
+    }
+    Ok(rgba)
+}
+
+fn parse_modifier(modifier: &str) -> Result<KeyboardModifier, String> {
+    match modifier.to_lowercase().as_str() {
+        "shift" => Ok(KeyboardModifier::WLR_MODIFIER_SHIFT),
+        "ctrl" | "control" => Ok(KeyboardModifier::WLR_MODIFIER_CTRL),
+        "alt" | "mod1" => Ok(KeyboardModifier::WLR_MODIFIER_ALT),
+        "logo" | "super" | "mod4" => Ok(KeyboardModifier::WLR_MODIFIER_LOGO),
+        _ => Err(format!("Unknown modifier \"{}\"", modifier))
+    }
+}
+
+/// Parses a chord of the form `Modifier+Modifier+Key`, where the key is the
+/// name of an XKB keysym.
+///
+/// The case of the name matters, because bindings with Shift get the upper
+/// case keysym (e.g `Ctrl+Shift+R`). Only names that don't match any keysym
+/// are looked up without caring about the case.
+fn parse_chord(chord: &str) -> Result<(KeyboardModifier, u32), String> {
+    let mut modifiers = KeyboardModifier::empty();
+    let mut parts = chord.split('+').map(str::trim).collect::<Vec<_>>();
+    let key = parts.pop().unwrap_or("");
+    for modifier in parts {
+        modifiers |= parse_modifier(modifier).map_err(|err| format!("{} in {}", err, chord))?;
+    }
+    let mut keysym = xkb::keysym_from_name(key, xkb::KEYSYM_NO_FLAGS);
+    if keysym == keysyms::KEY_NoSymbol {
+        keysym = xkb::keysym_from_name(key, xkb::KEYSYM_CASE_INSENSITIVE);
+    }
+    if keysym == keysyms::KEY_NoSymbol {
+        return Err(format!("Unknown key \"{}\" in {}", key, chord))
+    }
+    Ok((modifiers, keysym))
+}
+
+fn parse_sections(contents: &str) -> Result<Vec<Section>, String> {
+    let mut sections: Vec<Section> = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(format!("line {}: unterminated section header", line_number))
+            }
+            let header = line[1..line.len() - 1].trim();
+            let (name, label) = match header.find(char::is_whitespace) {
+                Some(split) => {
+                    let label = header[split..].trim();
+                    if label.len() < 2 || !label.starts_with('"') || !label.ends_with('"') {
+                        return Err(format!("line {}: section labels must be quoted", line_number))
+                    }
+                    (&header[..split], Some(label[1..label.len() - 1].to_string()))
+                }
+                None => (header, None)
+            };
+            sections.push(Section { name: name.to_string(),
+                                    label,
+                                    entries: Vec::new() });
+            continue
+        }
+        let split = line.find('=')
+                        .ok_or_else(|| format!("line {}: expected key = value", line_number))?;
+        let key = line[..split].trim().to_string();
+        let value = line[split + 1..].trim().to_string();
+        match sections.last_mut() {
+            Some(section) => section.entries.push((key, value)),
+            None => return Err(format!("line {}: {} is not in a section", line_number, key))
+        }
+    }
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sections_with_labels() {
+        let sections = parse_sections(r#"
+# A comment
+[keyboard]
+layout = de
+
+; Another comment
+[keyboard "Kinesis Advantage2 Keyboard"]
+variant = dvorak
+"#).unwrap();
+        assert_eq!(sections,
+                   vec![Section { name: "keyboard".into(),
+                                  label: None,
+                                  entries: vec![("layout".into(), "de".into())] },
+                        Section { name: "keyboard".into(),
+                                  label: Some("Kinesis Advantage2 Keyboard".into()),
+                                  entries: vec![("variant".into(), "dvorak".into())] }]);
+    }
+
+    #[test]
+    fn sections_errors() {
+        assert!(parse_sections("[keyboard").is_err());
+        assert!(parse_sections("[output eDP-1]").is_err());
+        assert!(parse_sections("layout = de").is_err());
+        assert!(parse_sections("[keyboard]\nlayout").is_err());
+    }
+
+    #[test]
+    fn chord() {
+        let ctrl_alt = KeyboardModifier::WLR_MODIFIER_CTRL | KeyboardModifier::WLR_MODIFIER_ALT;
+        assert_eq!(parse_chord("Ctrl+Alt+BackSpace"), Ok((ctrl_alt, keysyms::KEY_BackSpace)));
+        assert_eq!(parse_chord("Logo + Escape"),
+                   Ok((KeyboardModifier::WLR_MODIFIER_LOGO, keysyms::KEY_Escape)));
+        assert!(parse_chord("Hyper+Escape").is_err());
+        assert!(parse_chord("Ctrl+NotAKey").is_err());
+    }
+
+    #[test]
+    fn chord_with_shift() {
+        let ctrl_shift = KeyboardModifier::WLR_MODIFIER_CTRL |
+                         KeyboardModifier::WLR_MODIFIER_SHIFT;
+        assert_eq!(parse_chord("Ctrl+Shift+R"), Ok((ctrl_shift, keysyms::KEY_R)));
+        assert_eq!(parse_chord("Ctrl+r"),
+                   Ok((KeyboardModifier::WLR_MODIFIER_CTRL, keysyms::KEY_r)));
+        // Names that only exist in another case are still found.
+        assert_eq!(parse_chord("Ctrl+backspace"),
+                   Ok((KeyboardModifier::WLR_MODIFIER_CTRL, keysyms::KEY_BackSpace)));
+    }
+
+    #[test]
+    fn output_sections() {
+        let config = Config::parse(r#"
+[output "eDP-1"]
+mode = 1920x1080@60
+position = 1920,0
+scale = 1.5
+
+[output "Dell Inc. DELL U2415"]
+enabled = no
+"#).unwrap();
+        let edp = config.output_config("eDP-1", "", "");
+        assert_eq!(edp.mode,
+                   Some(OutputModeConfig { width: 1920,
+                                           height: 1080,
+                                           refresh: Some(60.0) }));
+        assert_eq!(edp.position, Some(Origin::new(1920, 0)));
+        assert_eq!(edp.scale, Some(1.5));
+        assert!(!config.output_config("DP-2", "Dell Inc.", "DELL U2415").enabled);
+        assert_eq!(config.output_config("DP-3", "", ""), OutputConfig::default());
+        assert!(Config::parse("[output]\nscale = 2").is_err());
+    }
+
+    #[test]
+    fn touch_sections() {
+        let config = Config::parse(r#"
+[touch]
+output = HDMI-A-1
+
+[touch "ELAN Touchscreen"]
+output = eDP-1
+"#).unwrap();
+        assert_eq!(config.touch_output_for("ELAN Touchscreen"), Some("eDP-1"));
+        assert_eq!(config.touch_output_for("Wacom Pen"), Some("HDMI-A-1"));
+        assert_eq!(Config::default().touch_output_for("ELAN Touchscreen"), None);
+    }
+}
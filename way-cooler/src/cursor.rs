@@ -1,4 +1,6 @@
-use wlroots::CursorHandler;
+use output::damage;
+use wlroots::utils::Edges;
+use wlroots::{CursorHandler, OutputHandle, XCursorManager};
 
 #[derive(Debug, Default)]
 pub struct CursorManager;
@@ -10,3 +12,40 @@ impl CursorManager {
         CursorManager::default()
     }
 }
+
+/// Creates the cursor manager for the cursor theme and size of the config.
+pub fn create_xcursor_manager(config: &::Config) -> XCursorManager {
+    let size = config.cursor_size();
+    damage::set_cursor_size(size);
+    XCursorManager::create(config.cursor_theme(), size).expect("Could not create xcursor manager")
+}
+
+/// Loads the cursor theme at the scale of every output, so the cursor is
+/// sharp on all of them.
+pub fn load_cursor_scales(xcursor_manager: &mut XCursorManager, outputs: &[OutputHandle]) {
+    xcursor_manager.load(1.0);
+    for output in outputs {
+        with_handles!([(output: {output})] => {
+            xcursor_manager.load(output.scale());
+        }).unwrap_or(())
+    }
+}
+
+/// The name of the cursor image for resizing from the edges.
+pub fn resize_cursor_image(edges: Edges) -> &'static str {
+    let top = edges.contains(Edges::WLR_EDGE_TOP);
+    let bottom = edges.contains(Edges::WLR_EDGE_BOTTOM);
+    let left = edges.contains(Edges::WLR_EDGE_LEFT);
+    let right = edges.contains(Edges::WLR_EDGE_RIGHT);
+    match (top, bottom, left, right) {
+        (true, _, true, _) => "top_left_corner",
+        (true, _, _, true) => "top_right_corner",
+        (_, true, true, _) => "bottom_left_corner",
+        (_, true, _, true) => "bottom_right_corner",
+        (true, ..) => "top_side",
+        (_, true, ..) => "bottom_side",
+        (_, _, true, _) => "left_side",
+        (_, _, _, true) => "right_side",
+        _ => "left_ptr"
+    }
+}
@@ -1,5 +1,7 @@
+use protocols::tablet;
 use wlroots::{Capability, CompositorHandle, InputManagerHandler, KeyboardHandle, KeyboardHandler,
-              PointerHandle, PointerHandler};
+              PointerHandle, PointerHandler, TabletPadHandle, TabletPadHandler, TabletToolHandle,
+              TabletToolHandler, TouchHandle, TouchHandler};
 
 pub struct InputManager;
 
@@ -18,6 +20,7 @@ impl InputManagerHandler for InputManager {
             @compositor = {compositor};
             @keyboard = {keyboard};
             let server: &mut ::Server = compositor.into();
+            ::apply_keymap(keyboard, &server.config);
             server.keyboards.push(keyboard.weak_reference());
             // Now that we have at least one keyboard, update the seat capabilities.
             @seat = {&server.seat.seat};
@@ -25,7 +28,7 @@ impl InputManagerHandler for InputManager {
             capabilities.insert(Capability::Keyboard);
             seat.set_capabilities(capabilities);
             seat.set_keyboard(keyboard.input_device()));
-        Some(Box::new(::Keyboard))
+        Some(Box::new(::Keyboard::default()))
     }
 
     fn pointer_added(&mut self,
@@ -51,4 +54,61 @@ impl InputManagerHandler for InputManager {
         );
         Some(Box::new(::Pointer))
     }
+
+    fn touch_added(&mut self,
+                   compositor: CompositorHandle,
+                   touch: TouchHandle)
+                   -> Option<Box<TouchHandler>> {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            with_handles!([(touch: {&touch}), (cursor: {&server.cursor})] => {
+                server.touches.push(touch.weak_reference());
+                // The cursor maps the touch points to the output of the device.
+                cursor.attach_input_device(touch.input_device());
+            }).unwrap();
+            if server.touches.len() == 1 {
+                with_handles!([(seat: {&mut server.seat.seat})] => {
+                    let mut capabilities = seat.capabilities();
+                    capabilities.insert(Capability::Touch);
+                    seat.set_capabilities(capabilities);
+                }).expect("Seat was destroyed");
+            }
+            let ::Server { ref cursor,
+                           ref touches,
+                           ref outputs,
+                           ref config,
+                           .. } = *server;
+            with_handles!([(cursor: {cursor})] => {
+                ::map_touch_devices(cursor, touches, outputs, config);
+            }).unwrap();
+        }).unwrap();
+        Some(Box::new(::Touch))
+    }
+
+    fn tablet_tool_added(&mut self,
+                         compositor: CompositorHandle,
+                         tool: TabletToolHandle)
+                         -> Option<Box<TabletToolHandler>> {
+        dehandle!(
+            @compositor = {compositor};
+            @tool = {tool};
+            let server: &mut ::Server = compositor.into();
+            tablet::tablet_added(tool);
+            @cursor = {&server.cursor};
+            // The cursor follows the tool, mapped to the layout.
+            cursor.attach_input_device(tool.input_device())
+        );
+        Some(Box::new(::TabletTool::default()))
+    }
+
+    fn tablet_pad_added(&mut self,
+                        _: CompositorHandle,
+                        pad: TabletPadHandle)
+                        -> Option<Box<TabletPadHandler>> {
+        dehandle!(
+            @pad = {pad};
+            tablet::pad_added(pad)
+        );
+        Some(Box::new(::TabletPad))
+    }
 }
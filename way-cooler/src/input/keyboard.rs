@@ -1,43 +1,94 @@
-use wlroots::{key_events::KeyEvent, xkbcommon::xkb::{KEY_Escape, KEY_Super_L, KEY_Super_R,
-              keysym_get_name}, Capability, CompositorHandle, KeyboardHandle, KeyboardHandler,
-              KeyboardModifier, WLR_KEY_PRESSED};
+use wlroots::{self, key_events::KeyEvent, xkbcommon::xkb::{self, keysym_get_name}, Capability,
+              CompositorHandle, KeyboardHandle, KeyboardHandler, KeyboardModifier,
+              WLR_KEY_PRESSED};
 
-pub struct Keyboard;
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    /// Keycodes whose press triggered a binding, so their release isn't
+    /// sent to the client either.
+    consumed_keys: Vec<u32>
+}
+
+/// Compiles the keymap configured for the keyboard and gives it to the
+/// keyboard.
+///
+/// If the keymap can't be compiled the keyboard keeps the keymap it has.
+pub fn apply_keymap(keyboard: &mut wlroots::Keyboard, config: &::Config) {
+    let device_name = keyboard.input_device().name();
+    let names = config.keymap_for(&device_name);
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    // Empty names make xkbcommon fall back to XKB_DEFAULT_*, then the system
+    // defaults.
+    let keymap = xkb::Keymap::new_from_names(&context,
+                                             names.rules.as_ref().map_or("", String::as_str),
+                                             names.model.as_ref().map_or("", String::as_str),
+                                             names.layout.as_ref().map_or("", String::as_str),
+                                             names.variant.as_ref().map_or("", String::as_str),
+                                             names.options.clone(),
+                                             xkb::KEYMAP_COMPILE_NO_FLAGS);
+    match keymap {
+        Some(keymap) => keyboard.set_keymap(&keymap),
+        None => error!("Could not compile the keymap {:?} for {}", names, device_name)
+    }
+}
 
-fn key_is_meta(key: u32) -> bool {
-    // TODO configure meta key
-    key == KEY_Super_L || key == KEY_Super_R
+fn run_binding_action(compositor: CompositorHandle, action: ::BindingAction) {
+    match action {
+        ::BindingAction::Terminate => wlroots::terminate(),
+        ::BindingAction::ReloadConfig => {
+            with_handles!([(compositor: {compositor})] => {
+                ::reload_config_when_idle(compositor);
+            }).unwrap()
+        }
+        ::BindingAction::SwitchVt(vt) => {
+            with_handles!([(compositor: {compositor})] => {
+                match compositor.backend.get_session() {
+                    Some(session) => session.change_vt(vt),
+                    None => warn!("Can't switch to VT {}, not running on a TTY", vt)
+                }
+            }).unwrap()
+        }
+    }
 }
 
 impl KeyboardHandler for Keyboard {
     fn on_key(&mut self, compositor: CompositorHandle, keyboard: KeyboardHandle, event: &KeyEvent) {
+        let keycode = event.keycode();
+        let pressed = event.key_state() == WLR_KEY_PRESSED;
+        let action = if pressed {
+            with_handles!([(compositor: {&compositor}), (keyboard: {&keyboard})] => {
+                let server: &mut ::Server = compositor.into();
+                let modifiers = keyboard.get_modifiers();
+                event.pressed_keys()
+                     .into_iter()
+                     .filter_map(|key| server.config.binding_action(modifiers, key))
+                     .next()
+            }).unwrap()
+        } else {
+            None
+        };
+
+        if let Some(action) = action {
+            // The client never saw the press, so it mustn't see the release.
+            self.consumed_keys.push(keycode);
+            run_binding_action(compositor, action);
+            return
+        }
+        if !pressed {
+            if let Some(index) = self.consumed_keys.iter().position(|&key| key == keycode) {
+                self.consumed_keys.remove(index);
+                return
+            }
+        }
+
         let modifiers = dehandle!(
             @compositor = {compositor};
-            if event.key_state() == WLR_KEY_PRESSED {
-                for key in event.pressed_keys() {
-                    // TODO Keep it hardcoded, make this configurable by awesome
-                    if key == KEY_Escape {
-                        ::wlroots::terminate();
-                    }
-                    if key_is_meta(key) {
-                        let server: &mut ::Server = compositor.into();
-                        server.seat.meta = true;
-                    }
-                }
-            } else {
-                for key in event.pressed_keys() {
-                    if key_is_meta(key) {
-                        let server: &mut ::Server = compositor.into();
-                        server.seat.meta = false;
-                    }
-                }
-            };
             let server: &mut ::Server = compositor.into();
             @seat = {&server.seat.seat};
             @keyboard = {keyboard};
             seat.set_keyboard(keyboard.input_device());
             seat.keyboard_notify_key(event.time_msec(),
-                                        event.keycode(),
+                                        keycode,
                                         event.key_state() as u32);
             seat.keyboard_send_modifiers(&mut keyboard.get_modifier_masks());
             keyboard.get_modifiers()
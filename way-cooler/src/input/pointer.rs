@@ -1,9 +1,39 @@
-use wlroots::{pointer_events::*, Capability, CompositorHandle, PointerHandle, PointerHandler,
-              WLR_BUTTON_RELEASED};
+use output::damage;
+use protocols::pointer_gestures::{self, Gesture};
+use protocols::{pointer_constraints, relative_pointer, window_management};
+use wlroots::{pointer_events::*, Capability, CompositorHandle, Layer, PointerHandle,
+              PointerHandler, SurfaceHandle, WLR_BUTTON_RELEASED};
 
 #[derive(Debug, Default)]
 pub struct Pointer;
 
+/// Begins a gesture of a touchpad on the surface under the cursor, unless
+/// the window manager claimed it.
+fn gesture_begin(compositor: CompositorHandle, gesture: Gesture, fingers: u32, time_msec: u32) {
+    if window_management::gesture_begin(gesture, fingers, time_msec) {
+        // Still ends the gesture the surface had in progress.
+        pointer_gestures::begin(gesture, None, fingers, time_msec);
+        return
+    }
+    let surface = with_handles!([(compositor: {compositor})] => {
+        let server: &mut ::Server = compositor.into();
+        surface_under_cursor(server)
+    }).unwrap();
+    pointer_gestures::begin(gesture, surface, fingers, time_msec);
+}
+
+/// The surface under the cursor, which gets the gestures of the pointer.
+fn surface_under_cursor(server: &mut ::Server) -> Option<SurfaceHandle> {
+    let ::Server { ref cursor,
+                   ref mut views,
+                   ref layer_surfaces,
+                   .. } = *server;
+    with_handles!([(cursor: {cursor})] => {
+        let (lx, ly) = cursor.coords();
+        ::Seat::surface_at(views, layer_surfaces, lx, ly).1
+    }).unwrap()
+}
+
 impl PointerHandler for Pointer {
     fn on_motion_absolute(&mut self,
                           compositor: CompositorHandle,
@@ -16,13 +46,17 @@ impl PointerHandler for Pointer {
                          ref mut xcursor_manager,
                          ref mut seat,
                          ref mut views,
+                         ref layer_surfaces,
                          .. } = *server;
             @cursor = {cursor};
             let (x, y) = event.pos();
+            damage::damage_cursor(cursor);
             cursor.warp_absolute(event.device(), x, y);
+            damage::damage_cursor(cursor);
             seat.update_cursor_position(cursor,
                                         xcursor_manager,
                                         views,
+                                        layer_surfaces,
                                         Some(event.time_msec())));
     }
 
@@ -34,42 +68,163 @@ impl PointerHandler for Pointer {
                          ref mut xcursor_manager,
                          ref mut seat,
                          ref mut views,
+                         ref layer_surfaces,
                          .. } = *server;
             @cursor = {cursor};
-            let (x, y) = event.delta();
+            let (lx, ly) = cursor.coords();
+            let (_, surface, sx, sy) = ::Seat::surface_at(views, layer_surfaces, lx, ly);
+            // Sent even if a constraint keeps the cursor from moving.
+            relative_pointer::relative_motion(surface.clone(), event.delta(), event.time_msec());
+            let focused = seat.focused.as_ref().map(|view| view.surface());
+            let (x, y) =
+                pointer_constraints::constrain_motion(surface.as_ref().map(|s| (s, sx, sy)),
+                                                      focused.as_ref(),
+                                                      event.delta());
+            damage::damage_cursor(cursor);
             cursor.move_to(event.device(), x, y);
+            damage::damage_cursor(cursor);
             seat.update_cursor_position(cursor,
                                         xcursor_manager,
                                         views,
+                                        layer_surfaces,
                                         Some(event.time_msec())));
     }
 
     fn on_button(&mut self, compositor: CompositorHandle, _: PointerHandle, event: &ButtonEvent) {
-        dehandle!(
-            @compositor = {compositor};
+        with_handles!([(compositor: {compositor})] => {
             let server: &mut ::Server = compositor.into();
-            let ::Server { ref cursor,
-                         ref mut views,
-                         ref mut seat,
-                         .. } = *server;
-            @cursor = {cursor};
+            let ::Server { ref mut cursor,
+                           ref mut xcursor_manager,
+                           ref mut views,
+                           ref mut seat,
+                           ref layer_surfaces,
+                           ref config,
+                           .. } = *server;
             if event.state() == WLR_BUTTON_RELEASED {
-                seat.action = None;
-                seat.send_button(event);
+                with_handles!([(cursor: {&mut *cursor})] => {
+                    seat.end_action(cursor, xcursor_manager, views, layer_surfaces);
+                }).unwrap();
+                if seat.grab_button == Some(event.button()) {
+                    seat.grab_button = None;
+                } else {
+                    seat.send_button(event);
+                }
                 return
             };
 
-            if let (Some(view), _, _, _) = ::Seat::view_at_pointer(views, cursor) {
-                seat.focus_view(view.clone(), views);
+            let (above_views, view) = with_handles!([(cursor: {&*cursor})] => {
+                // A fullscreen view on top covers the panels of the top layer.
+                let (lx, ly) = cursor.coords();
+                let (fullscreen, _) = ::split_fullscreen(views);
+                let fullscreen_view = ::Seat::view_at(fullscreen, lx, ly).0;
+                let layers: &[Layer] = if fullscreen_view.is_some() {
+                    &[Layer::Overlay]
+                } else {
+                    &[Layer::Overlay, Layer::Top]
+                };
+                let above_views = ::Seat::layer_surface_at(layer_surfaces, layers, lx, ly);
+                let view = fullscreen_view.or_else(|| ::Seat::view_at(views, lx, ly).0);
+                (above_views.is_some(), view)
+            }).unwrap();
+            if above_views {
+                seat.send_button(event);
+                return
+            }
+            let view = match view {
+                Some(view) => view,
+                None => {
+                    seat.clear_focus();
+                    return
+                }
+            };
+            seat.focus_view(view.clone(), views);
 
-                let meta_held_down = seat.meta;
-                if meta_held_down && event.button() == BTN_LEFT {
-                    seat.move_view(cursor, &view, None);
+            let border_edges = with_handles!([(cursor: {&*cursor})] => {
+                let (lx, ly) = cursor.coords();
+                ::Seat::border_edges_at(lx, ly, &view)
+            }).unwrap();
+            let modifier = config.pointer_modifier;
+            let grabbing = !modifier.is_empty() && seat.keyboard_modifiers().contains(modifier);
+            match event.button() {
+                BTN_LEFT if !border_edges.is_empty() => {
+                    seat.begin_resize(cursor, view, views, border_edges);
                 }
-                seat.send_button(event);
-            } else {
-                seat.clear_focus();
-            });
+                BTN_LEFT if grabbing => {
+                    with_handles!([(cursor: {&*cursor})] => {
+                        seat.move_view(cursor, &view, None);
+                    }).unwrap();
+                }
+                BTN_RIGHT if grabbing => {
+                    let edges = with_handles!([(cursor: {&*cursor})] => {
+                        ::Seat::resize_edges_at(cursor, &view)
+                    }).unwrap();
+                    seat.begin_resize(cursor, view, views, edges);
+                }
+                _ => {
+                    seat.send_button(event);
+                    return
+                }
+            }
+            // The client shouldn't also act on the press that grabbed it.
+            seat.grab_button = Some(event.button());
+        }).unwrap();
+    }
+
+    fn on_axis(&mut self, compositor: CompositorHandle, _: PointerHandle, event: &AxisEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            server.seat.send_axis(event);
+        }).unwrap();
+        window_management::pointer_axis(event.time_msec(),
+                                        event.source() as u32,
+                                        event.orientation() as u32,
+                                        event.delta(),
+                                        event.delta_discrete());
+    }
+
+    fn on_frame(&mut self, compositor: CompositorHandle, _: PointerHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            server.seat.send_pointer_frame();
+        }).unwrap();
+        window_management::pointer_frame();
+    }
+
+    fn on_swipe_begin(&mut self,
+                      compositor: CompositorHandle,
+                      _: PointerHandle,
+                      event: &SwipeBeginEvent) {
+        gesture_begin(compositor, Gesture::Swipe, event.fingers(), event.time_msec());
+    }
+
+    fn on_swipe_update(&mut self, _: CompositorHandle, _: PointerHandle, event: &SwipeUpdateEvent) {
+        let delta = event.delta();
+        pointer_gestures::update(Gesture::Swipe, delta, 1.0, 0.0, event.time_msec());
+        window_management::gesture_update(Gesture::Swipe, delta, 1.0, 0.0, event.time_msec());
+    }
+
+    fn on_swipe_end(&mut self, _: CompositorHandle, _: PointerHandle, event: &SwipeEndEvent) {
+        pointer_gestures::end(Gesture::Swipe, event.cancelled(), event.time_msec());
+        window_management::gesture_end(Gesture::Swipe, event.cancelled(), event.time_msec());
+    }
+
+    fn on_pinch_begin(&mut self,
+                      compositor: CompositorHandle,
+                      _: PointerHandle,
+                      event: &PinchBeginEvent) {
+        gesture_begin(compositor, Gesture::Pinch, event.fingers(), event.time_msec());
+    }
+
+    fn on_pinch_update(&mut self, _: CompositorHandle, _: PointerHandle, event: &PinchUpdateEvent) {
+        let (delta, scale, rotation) = (event.delta(), event.scale(), event.rotation());
+        let time_msec = event.time_msec();
+        pointer_gestures::update(Gesture::Pinch, delta, scale, rotation, time_msec);
+        window_management::gesture_update(Gesture::Pinch, delta, scale, rotation, time_msec);
+    }
+
+    fn on_pinch_end(&mut self, _: CompositorHandle, _: PointerHandle, event: &PinchEndEvent) {
+        pointer_gestures::end(Gesture::Pinch, event.cancelled(), event.time_msec());
+        window_management::gesture_end(Gesture::Pinch, event.cancelled(), event.time_msec());
     }
 
     fn destroyed(&mut self, compositor: CompositorHandle, pointer: PointerHandle) {
@@ -0,0 +1,180 @@
+use output::damage;
+use protocols::tablet::{self, ToolAxes};
+use wlroots::{pointer_events::{BTN_LEFT, BTN_MIDDLE, BTN_RIGHT}, tablet_pad_events,
+              tablet_tool_events::*, CompositorHandle, InputDevice, TabletPadHandle,
+              TabletPadHandler, TabletToolAxis, TabletToolHandle, TabletToolHandler,
+              TabletToolProximityState, TabletToolTipState, WLR_BUTTON_PRESSED,
+              WLR_BUTTON_RELEASED};
+
+/// The buttons on the side of a pen.
+const BTN_STYLUS: u32 = 0x14b;
+const BTN_STYLUS2: u32 = 0x14c;
+
+/// The tool of a tablet.
+///
+/// Over clients that use tablets it sends tablet events, everywhere else it
+/// acts like a pointer: the tip is the left button, and the buttons of the
+/// pen are the right and middle buttons.
+#[derive(Debug, Default)]
+pub struct TabletTool {
+    /// Where the tool is on the tablet, normalized between 0 and 1.
+    position: (f64, f64),
+    /// Whether the tip is down as the left button of the pointer. It stays a
+    /// pointer until the tip goes up.
+    pointer_down: bool
+}
+
+#[derive(Debug, Default)]
+pub struct TabletPad;
+
+impl TabletTool {
+    /// Moves the cursor to the tool, and sends the motion to the surface
+    /// under it.
+    fn motion(&mut self,
+              compositor: CompositorHandle,
+              tool: &TabletToolHandle,
+              device: &InputDevice,
+              axes: &ToolAxes,
+              time_msec: u32) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref cursor,
+                           ref mut xcursor_manager,
+                           ref mut seat,
+                           ref mut views,
+                           ref layer_surfaces,
+                           .. } = *server;
+            with_handles!([(cursor: {cursor})] => {
+                let (x, y) = self.position;
+                damage::damage_cursor(cursor);
+                cursor.warp_absolute(device, x, y);
+                damage::damage_cursor(cursor);
+                let (lx, ly) = cursor.coords();
+                let (_, surface, sx, sy) = ::Seat::surface_at(views, layer_surfaces, lx, ly);
+                let target = surface.map(|surface| (surface, sx, sy));
+                let sent = !self.pointer_down &&
+                           tablet::tool_motion(tool, target, (lx, ly), axes, time_msec);
+                if !sent {
+                    seat.update_cursor_position(cursor,
+                                                xcursor_manager,
+                                                views,
+                                                layer_surfaces,
+                                                Some(time_msec));
+                }
+            }).unwrap();
+        }).unwrap();
+    }
+}
+
+impl TabletToolHandler for TabletTool {
+    fn on_axis(&mut self, compositor: CompositorHandle, tool: TabletToolHandle, event: &AxisEvent) {
+        let updated = event.updated_axes();
+        let axis = |axis, value| if updated.contains(axis) { Some(value) } else { None };
+        // The position on the other axis is the one from before.
+        let (x, y) = event.position();
+        let (old_x, old_y) = self.position;
+        self.position = (axis(TabletToolAxis::WLR_TABLET_TOOL_AXIS_X, x).unwrap_or(old_x),
+                         axis(TabletToolAxis::WLR_TABLET_TOOL_AXIS_Y, y).unwrap_or(old_y));
+        let (tilt_x, tilt_y) = event.tilt();
+        let axes = ToolAxes { pressure: axis(TabletToolAxis::WLR_TABLET_TOOL_AXIS_PRESSURE,
+                                             event.pressure()),
+                              distance: axis(TabletToolAxis::WLR_TABLET_TOOL_AXIS_DISTANCE,
+                                             event.distance()),
+                              tilt_x: axis(TabletToolAxis::WLR_TABLET_TOOL_AXIS_TILT_X, tilt_x),
+                              tilt_y: axis(TabletToolAxis::WLR_TABLET_TOOL_AXIS_TILT_Y, tilt_y) };
+        self.motion(compositor, &tool, event.device(), &axes, event.time_msec());
+    }
+
+    fn on_proximity(&mut self,
+                    compositor: CompositorHandle,
+                    tool: TabletToolHandle,
+                    event: &ProximityEvent) {
+        if event.state() == TabletToolProximityState::WLR_TABLET_TOOL_PROXIMITY_IN {
+            self.position = event.position();
+            self.motion(compositor, &tool, event.device(), &ToolAxes::default(), event.time_msec());
+        } else {
+            tablet::tool_proximity_out(&tool, event.time_msec());
+        }
+    }
+
+    fn on_tip(&mut self, compositor: CompositorHandle, tool: TabletToolHandle, event: &TipEvent) {
+        let down = event.state() == TabletToolTipState::WLR_TABLET_TOOL_TIP_DOWN;
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref cursor,
+                           ref mut seat,
+                           ref mut views,
+                           ref layer_surfaces,
+                           .. } = *server;
+            if down {
+                // Touching a view focuses it, like clicking it.
+                let (lx, ly) = with_handles!([(cursor: {cursor})] => {
+                    cursor.coords()
+                }).unwrap();
+                if let (Some(view), ..) = ::Seat::surface_at(views, layer_surfaces, lx, ly) {
+                    seat.focus_view(view, views);
+                }
+            }
+            if !self.pointer_down && tablet::tool_tip(&tool, down, event.time_msec()) {
+                return
+            }
+            if self.pointer_down != down {
+                self.pointer_down = down;
+                let state = if down { WLR_BUTTON_PRESSED } else { WLR_BUTTON_RELEASED };
+                seat.send_pointer_button(event.time_msec(), BTN_LEFT, state as u32);
+            }
+        }).unwrap();
+    }
+
+    fn on_button(&mut self,
+                 compositor: CompositorHandle,
+                 tool: TabletToolHandle,
+                 event: &ButtonEvent) {
+        let pressed = event.state() == WLR_BUTTON_PRESSED;
+        let time_msec = event.time_msec();
+        if !self.pointer_down && tablet::tool_button(&tool, event.button(), pressed, time_msec) {
+            return
+        }
+        let button = match event.button() {
+            BTN_STYLUS => BTN_RIGHT,
+            BTN_STYLUS2 => BTN_MIDDLE,
+            button => button
+        };
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            server.seat.send_pointer_button(time_msec, button, event.state() as u32);
+        }).unwrap();
+    }
+
+    fn destroyed(&mut self, compositor: CompositorHandle, tool: TabletToolHandle) {
+        tablet::tablet_removed(&tool);
+        with_handles!([(compositor: {compositor}), (tool: {tool})] => {
+            let server: &mut ::Server = compositor.into();
+            with_handles!([(cursor: {&mut server.cursor})] => {
+                cursor.deattach_input_device(tool.input_device());
+            }).expect("Cursor was destroyed");
+        }).unwrap();
+    }
+}
+
+impl TabletPadHandler for TabletPad {
+    fn on_button(&mut self,
+                 compositor: CompositorHandle,
+                 pad: TabletPadHandle,
+                 event: &tablet_pad_events::ButtonEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            // The pad goes to the view with the keyboard focus.
+            let surface = server.seat.focused.as_ref().map(|view| view.surface());
+            tablet::pad_button(&pad,
+                               surface,
+                               event.button(),
+                               event.state() == WLR_BUTTON_PRESSED,
+                               event.time_msec());
+        }).unwrap();
+    }
+
+    fn destroyed(&mut self, _: CompositorHandle, pad: TabletPadHandle) {
+        tablet::pad_removed(&pad);
+    }
+}
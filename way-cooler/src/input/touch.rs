@@ -0,0 +1,105 @@
+use wlroots::{touch_events::*, Capability, CompositorHandle, Cursor, OutputHandle, TouchHandle,
+              TouchHandler};
+
+#[derive(Debug, Default)]
+pub struct Touch;
+
+/// Maps each touch device to the output it is configured for, or to the
+/// whole layout if there is none or it isn't connected.
+pub fn map_touch_devices(cursor: &mut Cursor,
+                         touches: &[TouchHandle],
+                         outputs: &[OutputHandle],
+                         config: &::Config) {
+    for touch in touches {
+        with_handles!([(touch: {touch})] => {
+            let device_name = touch.input_device().name();
+            let output = config.touch_output_for(&device_name).and_then(|name| {
+                outputs.iter().find(|output| {
+                    with_handles!([(output: {*output})] => {
+                        output.name() == name
+                    }).unwrap_or(false)
+                })
+            });
+            match output {
+                Some(output) => {
+                    with_handles!([(output: {output})] => {
+                        cursor.map_input_to_output(touch.input_device(), Some(output));
+                    }).unwrap_or(())
+                }
+                None => cursor.map_input_to_output(touch.input_device(), None)
+            }
+        }).unwrap_or(())
+    }
+}
+
+impl TouchHandler for Touch {
+    fn on_down(&mut self, compositor: CompositorHandle, _: TouchHandle, event: &DownEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref cursor,
+                           ref mut seat,
+                           ref mut views,
+                           ref layer_surfaces,
+                           .. } = *server;
+            with_handles!([(cursor: {cursor})] => {
+                let (x, y) = event.position();
+                let (lx, ly) = cursor.absolute_to_layout_coords(event.device(), x, y);
+                seat.touch_down(cursor,
+                                views,
+                                layer_surfaces,
+                                event.touch_id(),
+                                lx,
+                                ly,
+                                event.time_msec());
+            }).unwrap();
+        }).unwrap();
+    }
+
+    fn on_motion(&mut self, compositor: CompositorHandle, _: TouchHandle, event: &MotionEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref cursor,
+                           ref mut seat,
+                           .. } = *server;
+            with_handles!([(cursor: {cursor})] => {
+                let (x, y) = event.position();
+                let (lx, ly) = cursor.absolute_to_layout_coords(event.device(), x, y);
+                seat.touch_motion(cursor, event.touch_id(), lx, ly, event.time_msec());
+            }).unwrap();
+        }).unwrap();
+    }
+
+    fn on_up(&mut self, compositor: CompositorHandle, _: TouchHandle, event: &UpEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            server.seat.touch_up(event.touch_id(), event.time_msec());
+        }).unwrap();
+    }
+
+    fn on_cancel(&mut self, compositor: CompositorHandle, _: TouchHandle, event: &CancelEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            server.seat.touch_cancel(event.touch_id(), event.time_msec());
+        }).unwrap();
+    }
+
+    fn destroyed(&mut self, compositor: CompositorHandle, touch: TouchHandle) {
+        with_handles!([(compositor: {compositor}), (touch: {touch})] => {
+            let server: &mut ::Server = compositor.into();
+            let weak_reference = touch.weak_reference();
+            if let Some(index) = server.touches.iter().position(|t| *t == weak_reference) {
+                server.touches.remove(index);
+                if server.touches.len() == 0 {
+                    with_handles!([(seat: {&mut server.seat.seat})] => {
+                        let mut capabilities = seat.capabilities();
+                        capabilities.remove(Capability::Touch);
+                        seat.set_capabilities(capabilities);
+                    }).expect("Seat was destroyed")
+                }
+            }
+            with_handles!([(cursor: {&mut server.cursor})] => {
+                cursor.deattach_input_device(touch.input_device());
+            }).expect("Cursor was destroyed");
+        }).unwrap();
+    }
+}
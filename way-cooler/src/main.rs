@@ -11,17 +11,22 @@ extern crate getopts;
 extern crate log;
 extern crate nix;
 #[macro_use]
+extern crate wayland_sys;
+#[macro_use]
 pub(crate) extern crate wlroots;
 extern crate xcb;
 
+mod config;
 mod cursor;
 mod input;
 mod output;
+mod protocols;
 mod seat;
 mod shells;
 mod view;
 mod xwayland;
 
+pub use self::config::*;
 pub use self::cursor::*;
 pub use self::input::*;
 pub use self::output::*;
@@ -30,20 +35,37 @@ pub use self::shells::*;
 pub use self::view::*;
 pub use self::xwayland::*;
 
-use wlroots::{Compositor, CompositorBuilder, Cursor, CursorHandle, KeyboardHandle,
-              OutputHandle, OutputLayout, OutputLayoutHandle, PointerHandle, XCursorManager};
+use wlroots::backend::Backend;
+use wlroots::{Area, Compositor, CompositorBuilder, Cursor, CursorHandle, KeyboardHandle, Origin,
+              OutputHandle, OutputLayout, OutputLayoutHandle, PointerHandle, Size,
+              TouchHandle, XCursorManager};
 
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
 use std::rc::Rc;
 
 use std::{env, fs::File, io::{BufRead, BufReader}, path::Path, process::exit};
 
 use log::LogLevel;
+use output::damage;
 use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet};
+use wayland_sys::server::{wl_display, WAYLAND_SERVER_HANDLE};
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 const GIT_VERSION: &'static str = include_str!(concat!(env!("OUT_DIR"), "/git-version.txt"));
 const DRIVER_MOD_PATH: &'static str = "/proc/modules";
 const DEVICE_MOD_PATH: &'static str = "/sys/firmware/devicetree/base/model";
+const DEFAULT_HEADLESS_OUTPUTS: u32 = 1;
+const DEFAULT_HEADLESS_SIZE: (u32, u32) = (1280, 720);
+
+/// The virtual outputs to create when running without any real backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadlessConfig {
+    pub outputs: u32,
+    pub width: u32,
+    pub height: u32
+}
 
 #[derive(Debug)]
 pub struct Server {
@@ -53,8 +75,18 @@ pub struct Server {
     pub cursor: CursorHandle,
     pub keyboards: Vec<KeyboardHandle>,
     pub pointers: Vec<PointerHandle>,
+    pub touches: Vec<TouchHandle>,
     pub outputs: Vec<OutputHandle>,
-    pub views: Vec<Rc<View>>
+    pub views: Vec<Rc<View>>,
+    pub layer_surfaces: Vec<Rc<LayerSurface>>,
+    /// The area of each output that is not reserved by layer surfaces.
+    pub usable_areas: HashMap<OutputHandle, Area>,
+    pub config: Config,
+    /// Output configurations applied by clients, by connector name.
+    ///
+    /// They are kept apart from the config so that reloading it doesn't
+    /// undo them.
+    pub output_overrides: HashMap<String, OutputConfig>
 }
 
 impl Default for Server {
@@ -69,16 +101,20 @@ impl Default for Server {
                  cursor: CursorHandle::default(),
                  keyboards: Vec::default(),
                  pointers: Vec::default(),
+                 touches: Vec::default(),
                  outputs: Vec::default(),
-                 views: Vec::default() }
+                 views: Vec::default(),
+                 layer_surfaces: Vec::default(),
+                 usable_areas: HashMap::default(),
+                 config: Config::default(),
+                 output_overrides: HashMap::default() }
     }
 }
 
 impl Server {
     pub fn new(layout: OutputLayoutHandle, cursor: CursorHandle) -> Self {
-        let mut xcursor_manager =
-            XCursorManager::create("default".to_string(), 24).expect("Could not create xcursor \
-                                                                      manager");
+        let config = Config::load();
+        let mut xcursor_manager = create_xcursor_manager(&config);
         xcursor_manager.load(1.0);
         cursor.run(|c| xcursor_manager.set_cursor_image("left_ptr".to_string(), c))
               .unwrap();
@@ -86,8 +122,189 @@ impl Server {
         Server { xcursor_manager,
                  layout,
                  cursor,
+                 config,
                  ..Server::default() }
     }
+
+    /// Applies the output configuration to every output, rearranging what is
+    /// on them.
+    ///
+    /// Returns false if the mode of an output could not be set.
+    pub fn configure_outputs(&mut self) -> bool {
+        let Server { ref outputs,
+                     ref mut layout,
+                     ref cursor,
+                     ref touches,
+                     ref layer_surfaces,
+                     ref mut usable_areas,
+                     ref mut xcursor_manager,
+                     ref config,
+                     ref output_overrides,
+                     .. } = *self;
+        let mut modes_set = true;
+        for output in outputs {
+            with_handles!([(output: {output}), (layout: {&mut *layout})] => {
+                let output_config = output_config_for(output, config, output_overrides);
+                match configure_output(output, layout, &output_config) {
+                    Configured::Disabled => {
+                        damage::remove_output(&output.weak_reference());
+                        usable_areas.remove(&output.weak_reference());
+                    }
+                    configured => {
+                        modes_set &= configured != Configured::ModeFailed;
+                        damage::add_output(output, layout.get_box(output));
+                        arrange_layers(output, layout, layer_surfaces, usable_areas);
+                    }
+                }
+            }).unwrap_or(())
+        }
+        // The scales may have changed.
+        load_cursor_scales(xcursor_manager, outputs);
+        with_handles!([(cursor: {cursor})] => {
+            map_touch_devices(cursor, touches, outputs, config);
+        }).unwrap_or(());
+        protocols::output_management::outputs_changed();
+        modes_set
+    }
+
+    /// The area of the output a view is on, and the part of it that isn't
+    /// reserved by layer surfaces.
+    ///
+    /// A view is on the output that has its center, or on the first output
+    /// if it is outside of the layout.
+    pub fn output_areas_of(&mut self, view: &View) -> Option<(Area, Area)> {
+        let Server { ref outputs,
+                     ref mut layout,
+                     ref usable_areas,
+                     .. } = *self;
+        let Origin { x, y } = view.origin.get();
+        let Size { width, height } = view.get_size();
+        let (center_x, center_y) = (x + width / 2, y + height / 2);
+        let output_areas: Vec<(OutputHandle, Area)> =
+            outputs.iter()
+                   .filter_map(|output| {
+                       with_handles!([(output: {output}), (layout: {&mut *layout})] => {
+                           (output.weak_reference(), layout.get_box(output))
+                       }).ok()
+                   })
+                   .collect();
+        let &(ref output, output_area) =
+            output_areas.iter()
+                        .find(|&&(_, Area { origin, size })| {
+                                  center_x >= origin.x && center_x < origin.x + size.width &&
+                                  center_y >= origin.y && center_y < origin.y + size.height
+                              })
+                        .or(output_areas.first())?;
+        let usable_area = usable_areas.get(output).cloned().unwrap_or(output_area);
+        Some((output_area, usable_area))
+    }
+
+    /// Maximizes a view, or puts it back where it was.
+    pub fn set_maximized(&mut self, view: &View, maximized: bool) {
+        if let Some((output_area, usable_area)) = self.output_areas_of(view) {
+            view.set_maximized(maximized, output_area, usable_area);
+        }
+    }
+
+    /// Makes a view fullscreen, or puts it back where it was.
+    ///
+    /// The view covers the given output, or else the output it is on.
+    pub fn set_fullscreen(&mut self,
+                          view: &View,
+                          fullscreen: bool,
+                          output: Option<OutputHandle>) {
+        let output_areas = match output {
+            Some(ref output) if fullscreen => self.output_areas(output),
+            _ => None
+        };
+        let output_areas = output_areas.or_else(|| self.output_areas_of(view));
+        if let Some((output_area, usable_area)) = output_areas {
+            view.set_fullscreen(fullscreen, output_area, usable_area);
+        }
+    }
+
+    /// Applies the state a view asked for before it was mapped.
+    pub fn set_initial_state(&mut self, view: &View, maximized: bool, fullscreen: bool) {
+        if maximized {
+            self.set_maximized(view, true);
+        }
+        if fullscreen {
+            self.set_fullscreen(view, true, None);
+        }
+    }
+
+    /// The area of an output, and the part of it that isn't reserved by
+    /// layer surfaces.
+    pub fn output_areas(&mut self, output: &OutputHandle) -> Option<(Area, Area)> {
+        let Server { ref mut layout,
+                     ref usable_areas,
+                     .. } = *self;
+        let output_area = with_handles!([(output: {output}), (layout: {&mut *layout})] => {
+            layout.get_box(output)
+        }).ok()?;
+        let usable_area = usable_areas.get(output).cloned().unwrap_or(output_area);
+        Some((output_area, usable_area))
+    }
+
+    /// Minimizes a view, focusing the next one, or restores and focuses it.
+    pub fn set_minimized(&mut self, view: &Rc<View>, minimized: bool) {
+        if view.minimized.get() == minimized {
+            return
+        }
+        if minimized {
+            // While it is still drawn.
+            damage::damage_view(view);
+        }
+        view.minimized.set(minimized);
+        protocols::window_management::minimized_changed(view, minimized);
+        let Server { ref mut seat,
+                     ref mut views,
+                     ref layer_surfaces,
+                     ref cursor,
+                     ref mut xcursor_manager,
+                     .. } = *self;
+        if minimized {
+            if seat.focused.as_ref() == Some(view) {
+                seat.focus_next_view(views);
+            }
+        } else {
+            damage::damage_view(view);
+            seat.focus_view(view.clone(), views);
+        }
+        // The pointer is over a different view now.
+        with_handles!([(cursor: {cursor})] => {
+            seat.update_cursor_position(cursor, xcursor_manager, views, layer_surfaces, None)
+        }).unwrap_or(())
+    }
+
+    /// Reads the config file again and applies it to the live devices.
+    pub fn reload_config(&mut self) {
+        self.config = Config::load();
+        self.xcursor_manager = create_xcursor_manager(&self.config);
+        protocols::xdg_decoration::apply_config(&self.config);
+        for view in &self.views {
+            damage::damage_view(view);
+        }
+        self.configure_outputs();
+        {
+            // Show the image again from the new theme, unless a client set
+            // its own.
+            let Server { ref mut seat,
+                         ref cursor,
+                         ref mut xcursor_manager,
+                         .. } = *self;
+            if let Some(image) = seat.cursor_image.take() {
+                with_handles!([(cursor: {cursor})] => {
+                    seat.set_cursor_image(image, cursor, xcursor_manager);
+                }).unwrap_or(())
+            }
+        }
+        for keyboard in &self.keyboards {
+            with_handles!([(keyboard: {keyboard})] => {
+                apply_keymap(keyboard, &self.config);
+            }).unwrap_or(())
+        }
+    }
 }
 
 compositor_data!(Server);
@@ -95,6 +312,16 @@ compositor_data!(Server);
 fn main() {
     let mut opts = getopts::Options::new();
     opts.optflag("", "version", "show version information");
+    opts.optflag("", "headless", "run without a display, using virtual outputs");
+    opts.optopt("",
+                "headless-outputs",
+                "number of virtual outputs to create in headless mode",
+                "COUNT");
+    opts.optopt("",
+                "headless-size",
+                "size of the virtual outputs in headless mode",
+                "WIDTHxHEIGHT");
+    opts.optflag("", "no-xwayland", "don't start XWayland, X11 clients won't work");
     let matches = match opts.parse(env::args().skip(1)) {
         Ok(m) => m,
         Err(f) => {
@@ -110,6 +337,17 @@ fn main() {
         }
         return
     }
+    let headless = if matches.opt_present("headless") {
+        match parse_headless_config(&matches) {
+            Ok(config) => Some(config),
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        }
+    } else {
+        None
+    };
     println!("Launching way-cooler...");
 
     let sig_action = SigAction::new(SigHandler::Handler(sig_handle),
@@ -121,24 +359,45 @@ fn main() {
 
     init_logs();
     log_environment();
-    detect_proprietary();
-    detect_raspi();
+    if headless.is_none() {
+        detect_proprietary();
+        detect_raspi();
+    }
     ensure_good_env();
-    let compositor = setup_compositor();
-    assert!(compositor.xwayland.is_some());
+    let xwayland = !matches.opt_present("no-xwayland");
+    let compositor = setup_compositor(headless, xwayland);
+    if compositor.renderer.is_none() {
+        // wlroots only has a GLES2 renderer, nothing could be drawn.
+        error!("Could not create the GLES2 renderer, way-cooler can't render without it");
+        exit(1);
+    }
+    if xwayland && compositor.xwayland.is_none() {
+        warn!("Could not start XWayland, X11 clients won't work");
+    }
     compositor.run();
 }
 
-pub fn setup_compositor() -> Compositor {
+pub fn setup_compositor(headless: Option<HeadlessConfig>, xwayland: bool) -> Compositor {
     let layout = OutputLayout::create(Box::new(OutputLayoutManager::new()));
     let cursor = Cursor::create(Box::new(CursorManager::new()));
-    let mut compositor = CompositorBuilder::new().gles2(true)
-                                                 .data_device(true)
-                                                 .output_manager(Box::new(OutputManager::new()))
-                                                 .input_manager(Box::new(InputManager::new()))
-                                                 .xwayland(Box::new(XWaylandManager::new()))
-                                                 .xdg_shell_v6_manager(Box::new(XdgV6ShellManager))
-                                                 .build_auto(Server::new(layout, cursor));
+    let mut builder = CompositorBuilder::new().gles2(true)
+                                              .data_device(true)
+                                              .output_manager(Box::new(OutputManager::new()))
+                                              .input_manager(Box::new(InputManager::new()))
+                                              .xdg_shell_v6_manager(Box::new(XdgV6ShellManager))
+                                              .xdg_shell_manager(Box::new(XdgShellManager))
+                                              .layer_shell_manager(Box::new(LayerShellManager));
+    if xwayland {
+        builder = builder.xwayland(Box::new(XWaylandManager::new()));
+    }
+    let server = Server::new(layout, cursor);
+    let mut compositor = match headless {
+        Some(_) => builder.build_headless(server),
+        None => builder.build_auto(server)
+    };
+    if let Some(config) = headless {
+        add_headless_outputs(&mut compositor, config);
+    }
     // NOTE We need to create this afterwards because it needs the compositor
     // running to announce the seat.
     let seat = wlroots::Seat::create(&mut compositor,
@@ -148,9 +407,127 @@ pub fn setup_compositor() -> Compositor {
         let server: &mut Server = (&mut compositor).into();
         server.seat = Seat::new(seat);
     }
+    protocols::window_management::init(&mut compositor);
+    protocols::xdg_decoration::init(&mut compositor);
+    protocols::output_management::init(&mut compositor);
+    protocols::tablet::init(&mut compositor);
+    protocols::pointer_gestures::init(&mut compositor);
+    protocols::pointer_constraints::init(&mut compositor);
+    protocols::relative_pointer::init(&mut compositor);
+    {
+        let server: &mut Server = (&mut compositor).into();
+        protocols::xdg_decoration::apply_config(&server.config);
+    }
+    init_reload_signal(&mut compositor);
     compositor
 }
 
+/// Reloads the config when the compositor gets a SIGHUP.
+///
+/// This goes through the event loop, as the config can't be touched from
+/// inside of a signal handler.
+fn init_reload_signal(compositor: &mut Compositor) {
+    unsafe extern "C" fn reload(_: c_int, _: *mut c_void) -> c_int {
+        reload_server_config();
+        0
+    }
+    let source = unsafe {
+        let event_loop = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_display_get_event_loop,
+                                       compositor.display as *mut wl_display);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_event_loop_add_signal,
+                      event_loop,
+                      signal::SIGHUP as c_int,
+                      reload,
+                      ptr::null_mut())
+    };
+    if source.is_null() {
+        error!("Could not listen for SIGHUP, the config can't be reloaded");
+    }
+}
+
+/// Reloads the config once the event loop is idle.
+///
+/// Used by the reload binding: the keyboard that triggered it can't be
+/// reconfigured while it's dispatching the key, and would keep its keymap.
+pub fn reload_config_when_idle(compositor: &mut Compositor) {
+    unsafe extern "C" fn reload(_: *mut c_void) {
+        reload_server_config();
+    }
+    let source = unsafe {
+        let event_loop = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_display_get_event_loop,
+                                       compositor.display as *mut wl_display);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_event_loop_add_idle,
+                      event_loop,
+                      reload,
+                      ptr::null_mut())
+    };
+    if source.is_null() {
+        error!("Could not schedule reloading the config");
+    }
+}
+
+fn reload_server_config() {
+    if let Some(compositor) = wlroots::compositor_handle() {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut Server = compositor.into();
+            server.reload_config();
+        }).unwrap_or(())
+    }
+}
+
+/// Reads the size and number of the virtual outputs from the command line.
+fn parse_headless_config(matches: &getopts::Matches) -> Result<HeadlessConfig, String> {
+    let outputs = match matches.opt_str("headless-outputs") {
+        Some(count) => {
+            count.parse().map_err(|_| format!("Invalid number of headless outputs: {}", count))?
+        }
+        None => DEFAULT_HEADLESS_OUTPUTS
+    };
+    let (width, height) = match matches.opt_str("headless-size") {
+        Some(size) => {
+            let error = format!("Invalid headless output size: {}, expected WIDTHxHEIGHT", size);
+            parse_size(&size).ok_or(error)?
+        }
+        None => DEFAULT_HEADLESS_SIZE
+    };
+    Ok(HeadlessConfig { outputs,
+                        width,
+                        height })
+}
+
+/// Parses a size of the form `WIDTHxHEIGHT`, e.g `1920x1080`.
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let mut parts = size.splitn(2, 'x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    if width == 0 || height == 0 {
+        return None
+    }
+    Some((width, height))
+}
+
+/// Creates the virtual outputs of the headless backend.
+///
+/// They are announced through the `OutputManager` like any other output once
+/// the backend starts.
+fn add_headless_outputs(compositor: &mut Compositor, config: HeadlessConfig) {
+    match compositor.backend {
+        Backend::Headless(ref mut headless) => {
+            for _ in 0..config.outputs {
+                if headless.add_output(config.width, config.height).is_none() {
+                    error!("Could not create a {}x{} headless output",
+                           config.width, config.height);
+                }
+            }
+        }
+        _ => unreachable!("Headless outputs added to a different backend")
+    }
+}
+
 /// Formats the log strings properly
 fn log_format(record: &log::LogRecord) -> String {
     let color = match record.level() {
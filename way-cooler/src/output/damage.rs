@@ -0,0 +1,245 @@
+//! Damage tracking for the outputs.
+//!
+//! Anything that changes what is on screen adds the area it changed, in
+//! layout coordinates. When an output renders a frame it only repaints the
+//! damage that intersects with it, and skips the frame entirely if there
+//! is none.
+//!
+//! Like the window management protocol, this lives in a thread local
+//! because views are damaged from places that don't have the `Server`.
+
+use std::cell::{Cell, RefCell};
+use std::cmp::{max, min};
+use std::collections::HashMap;
+
+use wlroots::{Area, Cursor, Origin, Output, OutputHandle, OutputTransform, Size, SurfaceHandle};
+
+/// If an output has more damaged rectangles than this, the whole output is
+/// repainted instead.
+const MAX_DAMAGE_RECTS: usize = 16;
+
+/// How far around the cursor to damage when it moves, unless the cursor
+/// theme is bigger.
+///
+/// Client cursor images can be any size, this covers the common ones.
+const MIN_CURSOR_DAMAGE_SIZE: i32 = 64;
+
+thread_local! {
+    static DAMAGE: RefCell<HashMap<OutputHandle, OutputDamage>> = RefCell::new(HashMap::new());
+    /// How far around the cursor to damage, which covers the cursor image
+    /// and its hotspot.
+    static CURSOR_DAMAGE_SIZE: Cell<i32> = Cell::new(MIN_CURSOR_DAMAGE_SIZE);
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct OutputDamage {
+    /// Where the output is in the layout.
+    area: Area,
+    /// Damage since the last frame, in layout coordinates.
+    pending: Vec<Area>,
+    /// What was repainted in the last frame.
+    ///
+    /// The buffer we are about to draw into was last drawn to before that
+    /// frame, so it needs to be repainted too. This assumes the output is
+    /// double buffered.
+    previous: Vec<Area>
+}
+
+/// Starts tracking damage for an output, damaging all of it.
+pub fn add_output(output: &mut Output, area: Area) {
+    let handle = output.weak_reference();
+    DAMAGE.with(|damage| {
+                    let mut damage = damage.borrow_mut();
+                    let output_damage = damage.entry(handle).or_insert_with(OutputDamage::default);
+                    output_damage.area = area;
+                    output_damage.pending = vec![area];
+                });
+    output.schedule_frame();
+}
+
+/// Stops tracking damage for an output.
+pub fn remove_output(output: &OutputHandle) {
+    DAMAGE.with(|damage| damage.borrow_mut().remove(output));
+}
+
+/// Damages an area of the layout, scheduling a frame on every output it
+/// touches.
+pub fn damage_area(area: Area) {
+    if area.size.width <= 0 || area.size.height <= 0 {
+        return
+    }
+    let mut damaged_outputs = Vec::new();
+    DAMAGE.with(|damage| {
+        for (handle, output_damage) in damage.borrow_mut().iter_mut() {
+            if let Some(damaged) = intersection(output_damage.area, area) {
+                output_damage.pending.push(damaged);
+                damaged_outputs.push(handle.clone());
+            }
+        }
+    });
+    for output in damaged_outputs {
+        // If the output is in use it is already rendering, which is fine.
+        with_handles!([(output: {output})] => {
+            output.schedule_frame();
+        }).unwrap_or(())
+    }
+}
+
+/// Damages a whole output, e.g because its mode changed.
+pub fn damage_output(output: &OutputHandle) {
+    let area = DAMAGE.with(|damage| damage.borrow().get(output).map(|damage| damage.area));
+    if let Some(area) = area {
+        damage_area(area);
+    }
+}
+
+/// Damages everything a view covers now, and everything it covered the last
+/// time it was damaged.
+pub fn damage_view(view: &::View) {
+    damage_area(view.extents.get());
+    let Origin { x, y } = view.origin.get();
+    let mut extents: Option<Area> = None;
+    view.for_each_surface(&mut |surface: SurfaceHandle, sx, sy| {
+        let (width, height) = with_handles!([(surface: {surface})] => {
+            surface.current_state().size()
+        }).unwrap_or((0, 0));
+        let area = Area::new(Origin::new(x + sx, y + sy), Size::new(width, height));
+        damage_area(area);
+        extents = Some(extents.map(|extents| union(extents, area)).unwrap_or(area));
+    });
+    let border_width = view.border_width();
+    if border_width > 0 {
+        let Size { width, height } = view.get_size();
+        let border = Area::new(Origin::new(x - border_width, y - border_width),
+                               Size::new(width + border_width * 2, height + border_width * 2));
+        damage_area(border);
+        extents = Some(extents.map(|extents| union(extents, border)).unwrap_or(border));
+    }
+    view.extents.set(extents.unwrap_or_default());
+}
+
+/// Sets the size of the cursor theme, in layout coordinates, so that the
+/// cursor damage covers its images.
+pub fn set_cursor_size(size: u32) {
+    CURSOR_DAMAGE_SIZE.with(|damage_size| {
+                                damage_size.set(max(size as i32, MIN_CURSOR_DAMAGE_SIZE))
+                            });
+}
+
+/// Damages the area the cursor image could be covering.
+pub fn damage_cursor(cursor: &Cursor) {
+    let (x, y) = cursor.coords();
+    let size = CURSOR_DAMAGE_SIZE.with(|size| size.get());
+    let origin = Origin::new(x as i32 - size, y as i32 - size);
+    damage_area(Area::new(origin, Size::new(size * 2, size * 2)));
+}
+
+/// Gets the damage that needs to be repainted on the output this frame, in
+/// output buffer coordinates.
+///
+/// Returns `None` if nothing on the output changed since the last frame.
+pub fn take_frame_damage(output: &mut Output) -> Option<Vec<Area>> {
+    let handle = output.weak_reference();
+    let scale = output.scale();
+    let transform = output.get_transform();
+    let mode_size = output.current_mode().map(|mode| mode.dimensions());
+    DAMAGE.with(|damage| {
+        let mut damage = damage.borrow_mut();
+        let output_damage = damage.get_mut(&handle)?;
+        if output_damage.pending.is_empty() {
+            return None
+        }
+        let pending = output_damage.pending.drain(..).collect::<Vec<_>>();
+        let mut frame_damage = pending.clone();
+        frame_damage.extend(output_damage.previous.drain(..));
+        output_damage.previous = pending;
+        if frame_damage.len() > MAX_DAMAGE_RECTS {
+            frame_damage = vec![output_damage.area];
+        }
+        let Area { origin: Origin { x: output_x,
+                                    y: output_y },
+                   size: Size { width, height } } = output_damage.area;
+        // The size of the output once it is transformed, in buffer pixels.
+        let transformed_size = match mode_size {
+            Some((mode_width, mode_height)) if is_rotated(transform) => {
+                Size::new(mode_height as i32, mode_width as i32)
+            }
+            Some((mode_width, mode_height)) => Size::new(mode_width as i32, mode_height as i32),
+            None => {
+                Size::new((width as f32 * scale).ceil() as i32,
+                          (height as f32 * scale).ceil() as i32)
+            }
+        };
+        Some(frame_damage.into_iter()
+                         .map(|Area { origin: Origin { x, y },
+                                      size: Size { width, height } }| {
+                                  let (x, y) = (x - output_x, y - output_y);
+                                  let left = (x as f32 * scale).floor() as i32;
+                                  let top = (y as f32 * scale).floor() as i32;
+                                  let right = ((x + width) as f32 * scale).ceil() as i32;
+                                  let bottom = ((y + height) as f32 * scale).ceil() as i32;
+                                  let scaled = Area::new(Origin::new(left, top),
+                                                         Size::new(right - left, bottom - top));
+                                  transform_area(scaled, transform.invert(), transformed_size)
+                              })
+                         .collect())
+    })
+}
+
+/// Whether the transform swaps the width and the height.
+fn is_rotated(transform: OutputTransform) -> bool {
+    use wlroots::OutputTransform::*;
+    match transform {
+        WL_OUTPUT_TRANSFORM_90 |
+        WL_OUTPUT_TRANSFORM_270 |
+        WL_OUTPUT_TRANSFORM_FLIPPED_90 |
+        WL_OUTPUT_TRANSFORM_FLIPPED_270 => true,
+        _ => false
+    }
+}
+
+/// Transforms an area within a space of the given size, like
+/// `wlr_box_transform`.
+fn transform_area(area: Area, transform: OutputTransform, size: Size) -> Area {
+    use wlroots::OutputTransform::*;
+    let Area { origin: Origin { x, y },
+               size: Size { width, height } } = area;
+    let Size { width: space_width,
+               height: space_height } = size;
+    let (x, y) = match transform {
+        WL_OUTPUT_TRANSFORM_NORMAL => (x, y),
+        WL_OUTPUT_TRANSFORM_90 => (y, space_width - x - width),
+        WL_OUTPUT_TRANSFORM_180 => (space_width - x - width, space_height - y - height),
+        WL_OUTPUT_TRANSFORM_270 => (space_height - y - height, x),
+        WL_OUTPUT_TRANSFORM_FLIPPED => (space_width - x - width, y),
+        WL_OUTPUT_TRANSFORM_FLIPPED_90 => (y, x),
+        WL_OUTPUT_TRANSFORM_FLIPPED_180 => (x, space_height - y - height),
+        WL_OUTPUT_TRANSFORM_FLIPPED_270 => {
+            (space_height - y - height, space_width - x - width)
+        }
+    };
+    if is_rotated(transform) {
+        Area::new(Origin::new(x, y), Size::new(height, width))
+    } else {
+        Area::new(Origin::new(x, y), Size::new(width, height))
+    }
+}
+
+fn intersection(a: Area, b: Area) -> Option<Area> {
+    let x1 = max(a.origin.x, b.origin.x);
+    let y1 = max(a.origin.y, b.origin.y);
+    let x2 = min(a.origin.x + a.size.width, b.origin.x + b.size.width);
+    let y2 = min(a.origin.y + a.size.height, b.origin.y + b.size.height);
+    if x1 >= x2 || y1 >= y2 {
+        return None
+    }
+    Some(Area::new(Origin::new(x1, y1), Size::new(x2 - x1, y2 - y1)))
+}
+
+fn union(a: Area, b: Area) -> Area {
+    let x1 = min(a.origin.x, b.origin.x);
+    let y1 = min(a.origin.y, b.origin.y);
+    let x2 = max(a.origin.x + a.size.width, b.origin.x + b.size.width);
+    let y2 = max(a.origin.y + a.size.height, b.origin.y + b.size.height);
+    Area::new(Origin::new(x1, y1), Size::new(x2 - x1, y2 - y1))
+}
@@ -1,3 +1,4 @@
+pub mod damage;
 mod output;
 mod output_layout_manager;
 mod output_manager;
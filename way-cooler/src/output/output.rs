@@ -1,11 +1,17 @@
 use std::rc::Rc;
 
 use wlroots::utils::current_time;
-use wlroots::{project_box, Area, CompositorHandle, Origin, OutputHandle, OutputHandler,
+use wlroots::{project_box, Area, CompositorHandle, Layer, Origin, OutputHandle, OutputHandler,
               OutputLayoutHandle, Renderer, Size, SurfaceHandle};
 
+use super::damage;
 use ::Server;
 
+/// How far apart views that are moved off a removed output are cascaded.
+const CASCADE_STEP: i32 = 32;
+/// How many views are cascaded before starting from the corner again.
+const CASCADE_COUNT: i32 = 10;
+
 pub struct Output;
 
 impl OutputHandler for Output {
@@ -16,13 +22,133 @@ impl OutputHandler for Output {
             let state: &mut Server = compositor.data.downcast_mut().unwrap();
             let Server { ref mut layout,
             ref mut views,
+            ref layer_surfaces,
+            ref seat,
+            ref config,
             .. } = *state;
-            let renderer = compositor.renderer.as_mut().expect("gles2 disabled");
-            let mut renderer = renderer.render(output, None);
-            renderer.clear([0.25, 0.25, 0.25, 1.0]);
-            render_views(&mut renderer, layout, views)
+            let frame_damage = match damage::take_frame_damage(output) {
+                Some(frame_damage) => frame_damage,
+                // Nothing changed, keep what is on screen.
+                None => return
+            };
+            let renderer = match compositor.renderer.as_mut() {
+                Some(renderer) => renderer,
+                None => {
+                    error!("Can't draw a frame, the backend has no renderer");
+                    return
+                }
+            };
+            let mut renderer = renderer.render(output, Some(frame_damage.clone()));
+            for area in frame_damage {
+                renderer.render_scissor(Some(area));
+                renderer.clear([0.25, 0.25, 0.25, 1.0]);
+                let (fullscreen, views) = ::split_fullscreen(views);
+                render_layer(&mut renderer, layout, layer_surfaces, Layer::Background);
+                render_layer(&mut renderer, layout, layer_surfaces, Layer::Bottom);
+                render_views(&mut renderer, layout, views, seat.focused.as_ref(), config);
+                render_layer(&mut renderer, layout, layer_surfaces, Layer::Top);
+                render_views(&mut renderer, layout, fullscreen, seat.focused.as_ref(), config);
+                render_layer(&mut renderer, layout, layer_surfaces, Layer::Overlay);
+                render_drag_icons(&mut renderer, layout, &seat.drag_icons);
+            }
+            renderer.render_scissor(None)
             )
     }
+
+    fn destroyed(&mut self, compositor: CompositorHandle, output: OutputHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut Server = compositor.into();
+            remove_output(server, &output);
+        }).unwrap();
+    }
+}
+
+/// Forgets an output that was unplugged, moving what was on it to the
+/// outputs that are left.
+fn remove_output(server: &mut Server, output: &OutputHandle) {
+    let index = match server.outputs.iter().position(|o| o == output) {
+        Some(index) => index,
+        None => return
+    };
+    server.outputs.remove(index);
+    damage::remove_output(output);
+    server.usable_areas.remove(output);
+    with_handles!([(layout: {&mut server.layout}), (output: {output})] => {
+        layout.remove(output);
+    }).unwrap_or(());
+    // Layer surfaces belong to their output, they can't be moved elsewhere.
+    for layer_surface in server.layer_surfaces.iter().filter(|l| l.output == *output) {
+        with_handles!([(layer_surface: {&layer_surface.layer_surface})] => {
+            layer_surface.close();
+        }).unwrap_or(())
+    }
+    // Outputs that are placed automatically fill the gap it left.
+    server.configure_outputs();
+    rescue_views(server);
+}
+
+/// Moves the views that are no longer on any output, and the cursor, onto
+/// the first output.
+///
+/// The views are cascaded from the top left corner of the output, so they
+/// don't all end up on top of each other. Maximized and fullscreen views are
+/// maximized or made fullscreen again on that output.
+fn rescue_views(server: &mut Server) {
+    let Server { ref outputs,
+                 ref mut layout,
+                 ref usable_areas,
+                 ref views,
+                 ref mut cursor,
+                 .. } = *server;
+    let output_areas: Vec<(OutputHandle, Area)> =
+        outputs.iter()
+               .filter_map(|output| {
+                   with_handles!([(output: {output}), (layout: {&mut *layout})] => {
+                       if output.enabled() {
+                           Some((output.weak_reference(), layout.get_box(output)))
+                       } else {
+                           None
+                       }
+                   }).unwrap_or(None)
+               })
+               .collect();
+    let (output_area, target) = match output_areas.first() {
+        Some(&(ref output, area)) => (area, usable_areas.get(output).cloned().unwrap_or(area)),
+        // There is nowhere to put them.
+        None => return
+    };
+    let on_layout = |Origin { x, y }: Origin| {
+        output_areas.iter().any(|&(_, Area { origin, size })| {
+                                    x >= origin.x && x < origin.x + size.width &&
+                                    y >= origin.y && y < origin.y + size.height
+                                })
+    };
+    // Override redirect windows are placed by their X11 client.
+    let lost_views = views.iter()
+                          .filter(|view| !view.is_override_redirect())
+                          .filter(|view| !on_layout(view.origin.get()));
+    for (index, view) in lost_views.enumerate() {
+        let offset = (index as i32 % CASCADE_COUNT) * CASCADE_STEP;
+        let origin = Origin::new(target.origin.x + offset, target.origin.y + offset);
+        if view.maximized.get() || view.fullscreen.get() {
+            // Where it was before is on the output that is gone.
+            let size = view.saved_geometry.get().map_or_else(|| view.get_size(), |area| area.size);
+            view.saved_geometry.set(Some(Area::new(origin, size)));
+            view.apply_state(output_area, target);
+        } else {
+            view.move_to(origin);
+        }
+    }
+    with_handles!([(cursor: {cursor})] => {
+        let (x, y) = cursor.coords();
+        if !on_layout(Origin::new(x as i32, y as i32)) {
+            let Area { origin, size } = target;
+            cursor.warp(None,
+                        (origin.x + size.width / 2) as f64,
+                        (origin.y + size.height / 2) as f64);
+            damage::damage_cursor(cursor);
+        }
+    }).unwrap_or(())
 }
 
 fn render_surface(renderer: &mut Renderer,
@@ -32,55 +158,107 @@ fn render_surface(renderer: &mut Renderer,
                   ly: i32) {
     with_handles!([(surface: {surface}), (layout: {&mut *layout})] => {
         let (width, height) = surface.current_state().size();
-        let (render_width, render_height) =
-            (width * renderer.output.scale() as i32,
-            height * renderer.output.scale() as i32);
-        let render_box = Area::new(Origin::new(lx, ly),
-        Size::new(render_width,
-                  render_height));
-
-        if layout.intersects(renderer.output, render_box) {
+        let layout_box = Area::new(Origin::new(lx, ly), Size::new(width, height));
+        if layout.intersects(renderer.output, layout_box) {
+            let output_origin = layout.get_box(&mut *renderer.output).origin;
+            let render_box = output_box(layout_box, output_origin, renderer.output.scale());
             let transform = renderer.output.get_transform().invert();
             let matrix = project_box(render_box,
                                      transform,
                                      0.0,
-                                     renderer.output
-                                     .transform_matrix());
+                                     renderer.output.transform_matrix());
             renderer.render_texture_with_matrix(&surface.texture(), matrix);
             surface.send_frame_done(current_time());
         }
+    }).unwrap();
+}
 
+/// Converts a box in layout coordinates to the buffer coordinates of the
+/// output at `output_origin` with the given scale.
+///
+/// The edges are scaled rather than the size, so that fractional scales
+/// don't leave gaps between neighbouring boxes.
+fn output_box(area: Area, output_origin: Origin, scale: f32) -> Area {
+    let scaled = |value: i32| (value as f32 * scale).round() as i32;
+    let Area { origin: Origin { x, y }, size: Size { width, height } } = area;
+    let (x, y) = (x - output_origin.x, y - output_origin.y);
+    let (left, top) = (scaled(x), scaled(y));
+    Area::new(Origin::new(left, top),
+              Size::new(scaled(x + width) - left, scaled(y + height) - top))
+}
+
+/// Render all the layer surfaces in a layer.
+fn render_layer(renderer: &mut Renderer,
+                layout: &mut OutputLayoutHandle,
+                layer_surfaces: &[Rc<::LayerSurface>],
+                layer: Layer) {
+    for layer_surface in layer_surfaces.iter()
+                                       .filter(|l| l.layer == layer)
+                                       .filter(|l| l.mapped.get())
+    {
+        let Origin { x, y } = layer_surface.geometry.get().origin;
+        layer_surface.for_each_surface(&mut |mut surface: SurfaceHandle, sx, sy| {
+            render_surface(renderer, layout, &mut surface, x + sx, y + sy)
+        });
+    }
+}
+
+/// Render the border around a view that is decorated by the compositor.
+fn render_border(renderer: &mut Renderer,
+                 layout: &mut OutputLayoutHandle,
+                 view: &::View,
+                 color: [f32; 4]) {
+    let border_width = view.border_width();
+    if border_width <= 0 {
+        return
+    }
+    let output_origin = with_handles!([(layout: {&mut *layout})] => {
+        layout.get_box(&mut *renderer.output).origin
     }).unwrap();
+    let scale = renderer.output.scale();
+    let view_box = output_box(Area::new(view.origin.get(), view.get_size()), output_origin, scale);
+    let Area { origin: Origin { x: left, y: top }, size } = view_box;
+    let (right, bottom) = (left + size.width, top + size.height);
+    let border_width = (border_width as f32 * scale).round() as i32;
+    let (outer_width, inner_height) = (right - left + border_width * 2, bottom - top);
+    let sides =
+        [Area::new(Origin::new(left - border_width, top - border_width),
+                   Size::new(outer_width, border_width)),
+         Area::new(Origin::new(left - border_width, bottom), Size::new(outer_width, border_width)),
+         Area::new(Origin::new(left - border_width, top), Size::new(border_width, inner_height)),
+         Area::new(Origin::new(right, top), Size::new(border_width, inner_height))];
+    let matrix = renderer.output.transform_matrix();
+    for side in &sides {
+        renderer.render_colored_rect(*side, color, matrix);
+    }
+}
+
+/// Render the icons of drag and drop operations, above everything else.
+fn render_drag_icons(renderer: &mut Renderer,
+                     layout: &mut OutputLayoutHandle,
+                     drag_icons: &[::DragIcon]) {
+    for drag_icon in drag_icons.iter().filter(|icon| icon.mapped.get()) {
+        let Origin { x, y } = drag_icon.extents.get().origin;
+        render_surface(renderer, layout, &mut drag_icon.surface(), x, y);
+    }
 }
 
 /// Render all of the client views.
 fn render_views(renderer: &mut Renderer,
                 layout: &mut OutputLayoutHandle,
-                views: &mut Vec<Rc<::View>>) {
-    for view in views.iter_mut().rev() {
-        let origin = view.origin.get();
-        view.for_each_surface(&mut |surface: SurfaceHandle, sx, sy| {
-            dehandle!(
-                @surface = {surface};
-                @layout = {&*layout};
-                let (width, height) = surface.current_state().size();
-                let (render_width, render_height) =
-                    (width * renderer.output.scale() as i32,
-                     height * renderer.output.scale() as i32);
-                let render_box = Area::new(Origin::new(origin.x + sx, origin.y + sy),
-                                           Size::new(render_width,
-                                                     render_height));
-
-                if layout.intersects(renderer.output, render_box) {
-                    let transform = renderer.output.get_transform().invert();
-                    let matrix = project_box(render_box,
-                                             transform,
-                                             0.0,
-                                             renderer.output
-                                             .transform_matrix());
-                    renderer.render_texture_with_matrix(&surface.texture(), matrix);
-                    surface.send_frame_done(current_time());
-                })
+                views: &mut [Rc<::View>],
+                focused: Option<&Rc<::View>>,
+                config: &::Config) {
+    for view in views.iter_mut().rev().filter(|view| !view.minimized.get()) {
+        let color = if focused.map_or(false, |focused| **focused == **view) {
+            config.focused_border_color
+        } else {
+            config.unfocused_border_color
+        };
+        render_border(renderer, layout, view, color);
+        let Origin { x, y } = view.origin.get();
+        view.for_each_surface(&mut |mut surface: SurfaceHandle, sx, sy| {
+            render_surface(renderer, layout, &mut surface, x + sx, y + sy)
         });
     }
 }
@@ -1,9 +1,15 @@
-use wlroots::OutputLayoutHandler;
+use wlroots::{CompositorHandle, OutputLayoutHandle, OutputLayoutHandler};
+
+use protocols::output_management;
 
 #[derive(Debug, Default)]
 pub struct OutputLayoutManager;
 
-impl OutputLayoutHandler for OutputLayoutManager {}
+impl OutputLayoutHandler for OutputLayoutManager {
+    fn on_change(&mut self, _: CompositorHandle, _: OutputLayoutHandle) {
+        output_management::outputs_changed();
+    }
+}
 
 impl OutputLayoutManager {
     pub fn new() -> Self {
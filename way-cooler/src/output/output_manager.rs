@@ -1,4 +1,8 @@
-use wlroots::{CompositorHandle, OutputBuilder, OutputBuilderResult, OutputManagerHandler};
+use std::collections::HashMap;
+
+use output::damage;
+use wlroots::{CompositorHandle, Output, OutputBuilder, OutputBuilderResult, OutputLayout,
+              OutputManagerHandler, OutputMode, OutputTransform};
 
 pub struct OutputManager;
 
@@ -8,6 +12,88 @@ impl OutputManager {
     }
 }
 
+/// What applying the configuration of an output did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Configured {
+    Enabled,
+    /// The output is disabled and was taken out of the layout.
+    Disabled,
+    /// The output is enabled, but the backend refused to set its mode.
+    ModeFailed
+}
+
+/// The configuration of an output, preferring the one applied at runtime
+/// over the one from the config file.
+pub fn output_config_for(output: &Output,
+                         config: &::Config,
+                         overrides: &HashMap<String, ::OutputConfig>)
+                         -> ::OutputConfig {
+    let name = output.name();
+    match overrides.get(&name) {
+        Some(output_config) => *output_config,
+        None => config.output_config(&name, &output.make(), &output.model())
+    }
+}
+
+/// The mode of the output that matches the configured mode, taking the exact
+/// refresh rate or the fastest one if none was given.
+pub fn find_mode(output: &Output, mode: ::OutputModeConfig) -> Option<OutputMode> {
+    let refresh_mhz = mode.refresh.map(|refresh| (refresh * 1000.0).round() as i32);
+    let matches = |candidate: &OutputMode| {
+        let (width, height) = candidate.dimensions();
+        let same_refresh = refresh_mhz.map_or(true, |refresh| {
+                                                  (candidate.refresh() - refresh).abs() < 500
+                                              });
+        (width as i32, height as i32) == (mode.width, mode.height) && same_refresh
+    };
+    output.modes()
+          .into_iter()
+          .filter(|candidate| matches(candidate))
+          .max_by_key(|candidate| candidate.refresh())
+}
+
+/// Sets up an output with its configuration, and places it in the layout.
+pub fn configure_output(output: &mut Output,
+                        layout: &mut OutputLayout,
+                        output_config: &::OutputConfig)
+                        -> Configured {
+    if !output_config.enabled {
+        layout.remove(output);
+        output.enable(false);
+        return Configured::Disabled
+    }
+    output.enable(true);
+    let mut configured = Configured::Enabled;
+    if let Some(mode) = output_config.mode {
+        let mode_set = match find_mode(output, mode) {
+            Some(best_mode) => output.set_mode(best_mode),
+            None => {
+                warn!("{} has no {}x{} mode, trying a custom mode",
+                      output.name(),
+                      mode.width,
+                      mode.height);
+                let refresh_mhz = mode.refresh.map(|refresh| (refresh * 1000.0).round() as i32);
+                output.set_custom_mode(mode.width, mode.height, refresh_mhz.unwrap_or(0))
+            }
+        };
+        if !mode_set {
+            error!("Could not set the mode of {} to {}x{}",
+                   output.name(),
+                   mode.width,
+                   mode.height);
+            configured = Configured::ModeFailed;
+        }
+    }
+    output.set_scale(output_config.scale.unwrap_or(1.0));
+    output.transform(output_config.transform
+                                  .unwrap_or(OutputTransform::WL_OUTPUT_TRANSFORM_NORMAL));
+    match output_config.position {
+        Some(position) => layout.add(output, position),
+        None => layout.add_auto(output)
+    }
+    configured
+}
+
 impl OutputManagerHandler for OutputManager {
     fn output_added<'output>(&mut self,
                              compositor: CompositorHandle,
@@ -21,18 +107,30 @@ impl OutputManagerHandler for OutputManager {
             let ::Server { ref mut cursor,
                          ref mut layout,
                          ref mut xcursor_manager,
+                         ref layer_surfaces,
+                         ref mut usable_areas,
+                         ref outputs,
+                         ref touches,
+                         ref config,
+                         ref output_overrides,
                          .. } = *server;
             @layout = {layout};
             @cursor = {cursor};
             {
                 @output = {&res.output};
-                layout.add_auto(output);
+                let output_config = output_config_for(output, config, output_overrides);
+                if configure_output(output, layout, &output_config) != Configured::Disabled {
+                    damage::add_output(output, layout.get_box(output));
+                    ::arrange_layers(output, layout, layer_surfaces, usable_areas);
+                }
                 cursor.attach_output_layout(layout);
                 xcursor_manager.load(output.scale());
                 xcursor_manager.set_cursor_image("left_ptr".to_string(), cursor);
                 let (x, y) = cursor.coords();
                 cursor.warp(None, x, y)
             }
+            // A touch device may be waiting for this output.
+            ::map_touch_devices(cursor, touches, outputs, config);
             Some(res)
         )
     }
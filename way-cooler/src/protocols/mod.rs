@@ -0,0 +1,170 @@
+//! Wayland protocols that are implemented by Way Cooler itself instead of
+//! wlroots.
+//!
+//! The helpers here are shared by all of them.
+
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use wayland_sys::server::*;
+use wlroots::SurfaceHandle;
+
+pub mod output_management;
+pub mod pointer_constraints;
+pub mod pointer_gestures;
+pub mod relative_pointer;
+pub mod tablet;
+pub mod window_management;
+pub mod xdg_decoration;
+
+/// C interfaces generated from the protocol XML files by the build script.
+mod interfaces {
+    #![allow(dead_code, non_camel_case_types, non_upper_case_globals, unused_imports)]
+
+    pub use std::os::raw::{c_char, c_void};
+    pub use wayland_sys::common::*;
+
+    extern "C" {
+        /// Defined by wlroots, which implements xdg-shell. It's referenced by
+        /// `zxdg_decoration_manager_v1.get_toplevel_decoration`.
+        pub static xdg_toplevel_interface: wl_interface;
+        // Defined by libwayland-server, they are referenced by the tablet
+        // and pointer protocols.
+        pub static wl_pointer_interface: wl_interface;
+        pub static wl_region_interface: wl_interface;
+        pub static wl_seat_interface: wl_interface;
+        pub static wl_surface_interface: wl_interface;
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/window_management_interfaces.rs"));
+    include!(concat!(env!("OUT_DIR"), "/xdg_decoration_interfaces.rs"));
+    include!(concat!(env!("OUT_DIR"), "/output_management_interfaces.rs"));
+    include!(concat!(env!("OUT_DIR"), "/tablet_interfaces.rs"));
+    include!(concat!(env!("OUT_DIR"), "/pointer_gestures_interfaces.rs"));
+    include!(concat!(env!("OUT_DIR"), "/pointer_constraints_interfaces.rs"));
+    include!(concat!(env!("OUT_DIR"), "/relative_pointer_interfaces.rs"));
+}
+
+/// The `bind` function of a global, called when a client binds it.
+type BindFn = unsafe extern "C" fn(*mut wl_client, *mut c_void, u32, u32);
+
+/// Called when a resource is destroyed, for whatever reason.
+type DestroyFn = unsafe extern "C" fn(*mut wl_resource);
+
+/// Advertises a global on the display, logging an error with its name if
+/// that fails.
+fn create_global(display: *mut wl_display,
+                 interface: &wl_interface,
+                 version: u32,
+                 bind: BindFn,
+                 name: &str) {
+    let global = unsafe {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_global_create,
+                      display,
+                      interface,
+                      version as c_int,
+                      ptr::null_mut(),
+                      bind)
+    };
+    if global.is_null() {
+        error!("Could not create the {} global", name);
+    }
+}
+
+/// Creates the resource of a client that bound a global, with the
+/// implementation of its requests.
+///
+/// If that fails the client is told it's out of memory and None is returned.
+unsafe fn bind_resource(client: *mut wl_client,
+                        interface: &wl_interface,
+                        version: u32,
+                        id: u32,
+                        implementation: *const c_void,
+                        destroy: Option<DestroyFn>)
+                        -> Option<*mut wl_resource> {
+    new_resource(client, interface, version as c_int, id, implementation, destroy)
+}
+
+/// Creates a resource of a client with the version of `parent`, the object
+/// whose request or event creates it, and the implementation of its
+/// requests.
+///
+/// The id is the one the client picked in the request, or 0 for resources
+/// that are created by the compositor and sent in an event. If that fails
+/// the client is told it's out of memory and None is returned.
+unsafe fn create_resource(client: *mut wl_client,
+                          parent: *mut wl_resource,
+                          interface: &wl_interface,
+                          id: u32,
+                          implementation: *const c_void,
+                          destroy: Option<DestroyFn>)
+                          -> Option<*mut wl_resource> {
+    // Interfaces can have stopped at an older version than their parent.
+    let version = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, parent)
+        .min(interface.version);
+    new_resource(client, interface, version, id, implementation, destroy)
+}
+
+unsafe fn new_resource(client: *mut wl_client,
+                       interface: &wl_interface,
+                       version: c_int,
+                       id: u32,
+                       implementation: *const c_void,
+                       destroy: Option<DestroyFn>)
+                       -> Option<*mut wl_resource> {
+    let resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                 wl_resource_create,
+                                 client,
+                                 interface,
+                                 version,
+                                 id);
+    if resource.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return None
+    }
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  resource,
+                  implementation,
+                  ptr::null_mut(),
+                  destroy);
+    Some(resource)
+}
+
+/// The `destroy` request of most interfaces.
+unsafe extern "C" fn destroy_resource(_: *mut wl_client, resource: *mut wl_resource) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, resource);
+}
+
+unsafe fn post_error(resource: *mut wl_resource, code: u32, message: &str) {
+    let message = CString::new(message).unwrap_or_default();
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_error,
+                  resource,
+                  code,
+                  message.as_ptr());
+}
+
+/// Sends an event whose only argument is a string.
+unsafe fn send_string(resource: *mut wl_resource, opcode: u32, string: &str) {
+    let string = CString::new(string).unwrap_or_default();
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  opcode,
+                  string.as_ptr());
+}
+
+/// The `wl_surface` resource of a surface, unless it was destroyed.
+pub fn surface_resource(surface: &SurfaceHandle) -> Option<*mut wl_resource> {
+    with_handles!([(surface: {surface})] => {
+        unsafe { (*surface.as_ptr()).resource as *mut wl_resource }
+    }).ok()
+}
+
+/// Converts to the `wl_fixed_t` of the protocols.
+fn to_fixed(value: f64) -> i32 {
+    (value * 256.0).round() as i32
+}
@@ -0,0 +1,832 @@
+//! Implementation of `zwlr_output_manager_v1`, which lets tools like
+//! wlr-randr and kanshi read and change the output configuration.
+//!
+//! The outputs are advertised as heads. Whenever an output or the layout
+//! changes every head is finished and advertised again, instead of sending
+//! only what changed. Clients treat both the same way.
+//!
+//! Applied configurations are stored as overrides of the config file, so
+//! they go through the same path as the config file and survive reloading
+//! it.
+
+use std::cell::RefCell;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use wayland_sys::server::*;
+use wlroots::{self, Compositor, Origin, OutputHandle, OutputTransform};
+
+use super::interfaces::{zwlr_output_configuration_head_v1_interface,
+                        zwlr_output_configuration_v1_interface, zwlr_output_head_v1_interface,
+                        zwlr_output_manager_v1_interface, zwlr_output_mode_v1_interface};
+use super::{bind_resource, create_global, create_resource, destroy_resource, post_error,
+            send_string, to_fixed};
+use {OutputConfig, OutputModeConfig};
+
+/// The highest version of the protocol that we support.
+const VERSION: u32 = 1;
+
+/// Opcodes of the events of `zwlr_output_manager_v1`.
+const HEAD_EVENT: u32 = 0;
+const DONE_EVENT: u32 = 1;
+const MANAGER_FINISHED_EVENT: u32 = 2;
+
+/// Opcodes of the events of `zwlr_output_head_v1`.
+const NAME_EVENT: u32 = 0;
+const DESCRIPTION_EVENT: u32 = 1;
+const PHYSICAL_SIZE_EVENT: u32 = 2;
+const MODE_EVENT: u32 = 3;
+const ENABLED_EVENT: u32 = 4;
+const CURRENT_MODE_EVENT: u32 = 5;
+const POSITION_EVENT: u32 = 6;
+const TRANSFORM_EVENT: u32 = 7;
+const SCALE_EVENT: u32 = 8;
+const HEAD_FINISHED_EVENT: u32 = 9;
+
+/// Opcodes of the events of `zwlr_output_mode_v1`.
+const SIZE_EVENT: u32 = 0;
+const REFRESH_EVENT: u32 = 1;
+const MODE_FINISHED_EVENT: u32 = 3;
+
+/// Opcodes of the events of `zwlr_output_configuration_v1`.
+const SUCCEEDED_EVENT: u32 = 0;
+const FAILED_EVENT: u32 = 1;
+const CANCELLED_EVENT: u32 = 2;
+
+/// Errors of `zwlr_output_configuration_v1`.
+const ALREADY_CONFIGURED_HEAD_ERROR: u32 = 1;
+const UNCONFIGURED_HEAD_ERROR: u32 = 2;
+const ALREADY_USED_ERROR: u32 = 3;
+
+/// Errors of `zwlr_output_configuration_head_v1`.
+const ALREADY_SET_ERROR: u32 = 1;
+const INVALID_MODE_ERROR: u32 = 2;
+const INVALID_CUSTOM_MODE_ERROR: u32 = 3;
+const INVALID_TRANSFORM_ERROR: u32 = 4;
+const INVALID_SCALE_ERROR: u32 = 5;
+
+/// Opcodes of the requests of `zwlr_output_configuration_head_v1`, used to
+/// remember which properties were already set.
+const SET_MODE_REQUEST: u32 = 0;
+const SET_POSITION_REQUEST: u32 = 2;
+const SET_TRANSFORM_REQUEST: u32 = 3;
+const SET_SCALE_REQUEST: u32 = 4;
+
+thread_local! {
+    static STATE: RefCell<OutputManagement> = RefCell::new(OutputManagement::default());
+}
+
+#[derive(Debug)]
+struct OutputManagement {
+    display: *mut wl_display,
+    /// All the bound `zwlr_output_manager_v1` resources.
+    managers: Vec<*mut wl_resource>,
+    heads: Vec<Head>,
+    modes: Vec<Mode>,
+    configurations: Vec<Configuration>,
+    /// Incremented every time the heads are advertised again. Configurations
+    /// created for an older serial are cancelled.
+    serial: u32,
+    update_scheduled: bool
+}
+
+/// A `zwlr_output_head_v1` resource.
+#[derive(Debug)]
+struct Head {
+    resource: *mut wl_resource,
+    manager: *mut wl_resource,
+    output: OutputHandle,
+    /// The configuration of the output when it was advertised.
+    current: OutputConfig
+}
+
+/// A `zwlr_output_mode_v1` resource.
+#[derive(Debug)]
+struct Mode {
+    resource: *mut wl_resource,
+    head: *mut wl_resource,
+    mode: OutputModeConfig
+}
+
+/// A `zwlr_output_configuration_v1` resource.
+#[derive(Debug)]
+struct Configuration {
+    resource: *mut wl_resource,
+    manager: *mut wl_resource,
+    serial: u32,
+    /// Whether it has been applied or tested already.
+    used: bool,
+    enabled: Vec<ConfigurationHead>,
+    /// The head resources that are disabled by this configuration.
+    disabled: Vec<*mut wl_resource>
+}
+
+/// A `zwlr_output_configuration_head_v1` resource.
+#[derive(Debug)]
+struct ConfigurationHead {
+    resource: *mut wl_resource,
+    head: *mut wl_resource,
+    config: OutputConfig,
+    /// The requests that were already used to set a property.
+    set: Vec<u32>
+}
+
+/// Everything that is advertised about an output.
+#[derive(Debug)]
+struct HeadState {
+    output: OutputHandle,
+    name: String,
+    description: String,
+    physical_size: (i32, i32),
+    modes: Vec<OutputModeConfig>,
+    current_mode: Option<OutputModeConfig>,
+    config: OutputConfig
+}
+
+#[repr(C)]
+struct ManagerImplementation {
+    create_configuration: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, u32),
+    stop: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+#[repr(C)]
+struct ConfigurationImplementation {
+    enable_head: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, *mut wl_resource),
+    disable_head: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, *mut wl_resource),
+    apply: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    test: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+#[repr(C)]
+struct ConfigurationHeadImplementation {
+    set_mode: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, *mut wl_resource),
+    set_custom_mode: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32, i32, i32),
+    set_position: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32, i32),
+    set_transform: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32),
+    set_scale: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32)
+}
+
+static MANAGER_IMPLEMENTATION: ManagerImplementation =
+    ManagerImplementation { create_configuration,
+                            stop };
+
+static CONFIGURATION_IMPLEMENTATION: ConfigurationImplementation =
+    ConfigurationImplementation { enable_head,
+                                  disable_head,
+                                  apply,
+                                  test,
+                                  destroy: destroy_resource };
+
+static CONFIGURATION_HEAD_IMPLEMENTATION: ConfigurationHeadImplementation =
+    ConfigurationHeadImplementation { set_mode,
+                                      set_custom_mode,
+                                      set_position,
+                                      set_transform,
+                                      set_scale };
+
+impl Default for OutputManagement {
+    fn default() -> Self {
+        OutputManagement { display: ptr::null_mut(),
+                           managers: Vec::new(),
+                           heads: Vec::new(),
+                           modes: Vec::new(),
+                           configurations: Vec::new(),
+                           serial: 0,
+                           update_scheduled: false }
+    }
+}
+
+impl OutputManagement {
+    /// Advertises the outputs to a manager, followed by a `done` event.
+    unsafe fn announce(&mut self, manager: *mut wl_resource, heads: &[HeadState]) {
+        let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, manager);
+        for head_state in heads {
+            let head = create_resource(client,
+                                       manager,
+                                       &zwlr_output_head_v1_interface,
+                                       0,
+                                       ptr::null(),
+                                       Some(head_destroyed));
+            let head = match head {
+                Some(head) => head,
+                None => return
+            };
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          manager,
+                          HEAD_EVENT,
+                          head);
+            send_string(head, NAME_EVENT, &head_state.name);
+            send_string(head, DESCRIPTION_EVENT, &head_state.description);
+            let (width, height) = head_state.physical_size;
+            if width > 0 && height > 0 {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              head,
+                              PHYSICAL_SIZE_EVENT,
+                              width,
+                              height);
+            }
+            let mut current_mode = ptr::null_mut();
+            for mode in &head_state.modes {
+                let resource = create_resource(client,
+                                               manager,
+                                               &zwlr_output_mode_v1_interface,
+                                               0,
+                                               ptr::null(),
+                                               Some(mode_destroyed));
+                let resource = match resource {
+                    Some(resource) => resource,
+                    None => return
+                };
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              head,
+                              MODE_EVENT,
+                              resource);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              resource,
+                              SIZE_EVENT,
+                              mode.width,
+                              mode.height);
+                if let Some(refresh) = mode.refresh {
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                  wl_resource_post_event,
+                                  resource,
+                                  REFRESH_EVENT,
+                                  to_mhz(refresh));
+                }
+                if head_state.current_mode == Some(*mode) {
+                    current_mode = resource;
+                }
+                self.modes.push(Mode { resource,
+                                       head,
+                                       mode: *mode });
+            }
+            let config = head_state.config;
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          head,
+                          ENABLED_EVENT,
+                          config.enabled as c_int);
+            if config.enabled {
+                if !current_mode.is_null() {
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                  wl_resource_post_event,
+                                  head,
+                                  CURRENT_MODE_EVENT,
+                                  current_mode);
+                }
+                let Origin { x, y } = config.position.unwrap_or(Origin::new(0, 0));
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              head,
+                              POSITION_EVENT,
+                              x,
+                              y);
+                let transform = config.transform
+                                      .unwrap_or(OutputTransform::WL_OUTPUT_TRANSFORM_NORMAL);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              head,
+                              TRANSFORM_EVENT,
+                              transform as c_int);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              head,
+                              SCALE_EVENT,
+                              to_fixed(config.scale.unwrap_or(1.0) as f64));
+            }
+            self.heads.push(Head { resource: head,
+                                   manager,
+                                   output: head_state.output.clone(),
+                                   current: config });
+        }
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      manager,
+                      DONE_EVENT,
+                      self.serial);
+    }
+}
+
+/// Advertises the output manager global on the compositor's display.
+pub fn init(compositor: &mut Compositor) {
+    let display = compositor.display as *mut wl_display;
+    STATE.with(|state| state.borrow_mut().display = display);
+    create_global(display,
+                  unsafe { &zwlr_output_manager_v1_interface },
+                  VERSION,
+                  bind,
+                  "output manager");
+}
+
+/// Tells the output managers that an output or the layout changed.
+///
+/// The update is sent once the event loop is idle, so several changes are
+/// sent together and the outputs aren't in use when they are read.
+pub fn outputs_changed() {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if state.update_scheduled || state.display.is_null() {
+            return
+        }
+        unsafe {
+            let event_loop = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                           wl_display_get_event_loop,
+                                           state.display);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_loop_add_idle,
+                          event_loop,
+                          send_update,
+                          ptr::null_mut());
+        }
+        state.update_scheduled = true;
+    });
+}
+
+/// Finishes every head and mode, and advertises the outputs again.
+///
+/// The finished resources are forgotten, which makes them inert. They are
+/// destroyed by the client.
+unsafe extern "C" fn send_update(_: *mut c_void) {
+    let (heads, modes) = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.update_scheduled = false;
+        state.serial += 1;
+        (state.heads.drain(..).map(|head| head.resource).collect::<Vec<_>>(),
+         state.modes.drain(..).map(|mode| mode.resource).collect::<Vec<_>>())
+    });
+    for mode in modes {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      mode,
+                      MODE_FINISHED_EVENT);
+    }
+    for head in heads {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      head,
+                      HEAD_FINISHED_EVENT);
+    }
+    let head_states = head_states();
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   for manager in state.managers.clone() {
+                       state.announce(manager, &head_states);
+                   }
+               });
+}
+
+/// Reads the state of every output from the compositor.
+fn head_states() -> Vec<HeadState> {
+    let compositor = match wlroots::compositor_handle() {
+        Some(compositor) => compositor,
+        None => return Vec::new()
+    };
+    with_handles!([(compositor: {compositor})] => {
+        let server: &mut ::Server = compositor.into();
+        let ::Server { ref outputs,
+                       ref mut layout,
+                       .. } = *server;
+        outputs.iter().filter_map(|output| {
+            with_handles!([(output: {output}), (layout: {&mut *layout})] => {
+                let modes = output.modes()
+                                  .into_iter()
+                                  .map(|mode| {
+                                      let (width, height) = mode.dimensions();
+                                      OutputModeConfig { width: width as i32,
+                                                         height: height as i32,
+                                                         refresh: from_mhz(mode.refresh()) }
+                                  })
+                                  .collect();
+                let current_mode = output.current_mode().map(|mode| {
+                    let (width, height) = mode.dimensions();
+                    OutputModeConfig { width: width as i32,
+                                       height: height as i32,
+                                       refresh: from_mhz(mode.refresh()) }
+                });
+                let enabled = output.enabled();
+                let position = layout.get_box(output).origin;
+                let config = OutputConfig { enabled,
+                                            mode: current_mode,
+                                            position: Some(position),
+                                            scale: Some(output.scale()),
+                                            transform: Some(output.get_transform()) };
+                HeadState { output: output.weak_reference(),
+                            name: output.name(),
+                            description: format!("{} {} ({})",
+                                                 output.make(),
+                                                 output.model(),
+                                                 output.name()),
+                            physical_size: output.physical_dimensions(),
+                            modes,
+                            current_mode,
+                            config }
+            }).ok()
+        }).collect()
+    }).unwrap_or_else(|_| Vec::new())
+}
+
+/// Checks that the configurations would apply to their outputs.
+///
+/// Modes have to be one of the modes of the output, unless the backend
+/// doesn't have a list of modes and takes any size.
+fn validate_output_configs(configs: &[(OutputHandle, OutputConfig)]) -> bool {
+    configs.iter().all(|&(ref output, config)| {
+        with_handles!([(output: {output})] => {
+            match config.mode {
+                Some(mode) if config.enabled => {
+                    output.modes().is_empty() || ::find_mode(output, mode).is_some()
+                }
+                _ => true
+            }
+        }).unwrap_or(false)
+    })
+}
+
+/// Applies the configurations to their outputs as overrides of the config.
+///
+/// If an output can't be configured, the previous overrides are restored.
+fn apply_output_configs(configs: Vec<(OutputHandle, OutputConfig)>) -> bool {
+    if !validate_output_configs(&configs) {
+        return false
+    }
+    let compositor = match wlroots::compositor_handle() {
+        Some(compositor) => compositor,
+        None => return false
+    };
+    with_handles!([(compositor: {compositor})] => {
+        let server: &mut ::Server = compositor.into();
+        let previous_overrides = server.output_overrides.clone();
+        for (output, config) in configs {
+            let name = match with_handles!([(output: {output})] => { output.name() }) {
+                Ok(name) => name,
+                // It was unplugged, the configuration is outdated.
+                Err(_) => return false
+            };
+            server.output_overrides.insert(name, config);
+        }
+        if server.configure_outputs() {
+            return true
+        }
+        server.output_overrides = previous_overrides;
+        server.configure_outputs();
+        false
+    }).unwrap_or(false)
+}
+
+fn from_fixed(value: i32) -> f32 {
+    value as f32 / 256.0
+}
+
+fn to_mhz(refresh: f32) -> i32 {
+    (refresh * 1000.0).round() as i32
+}
+
+fn from_mhz(refresh: i32) -> Option<f32> {
+    if refresh > 0 {
+        Some(refresh as f32 / 1000.0)
+    } else {
+        None
+    }
+}
+
+fn transform_from_int(transform: i32) -> Option<OutputTransform> {
+    Some(match transform {
+             0 => OutputTransform::WL_OUTPUT_TRANSFORM_NORMAL,
+             1 => OutputTransform::WL_OUTPUT_TRANSFORM_90,
+             2 => OutputTransform::WL_OUTPUT_TRANSFORM_180,
+             3 => OutputTransform::WL_OUTPUT_TRANSFORM_270,
+             4 => OutputTransform::WL_OUTPUT_TRANSFORM_FLIPPED,
+             5 => OutputTransform::WL_OUTPUT_TRANSFORM_FLIPPED_90,
+             6 => OutputTransform::WL_OUTPUT_TRANSFORM_FLIPPED_180,
+             7 => OutputTransform::WL_OUTPUT_TRANSFORM_FLIPPED_270,
+             _ => return None
+         })
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, _: *mut c_void, version: u32, id: u32) {
+    let resource = bind_resource(client,
+                                 &zwlr_output_manager_v1_interface,
+                                 version,
+                                 id,
+                                 &MANAGER_IMPLEMENTATION as *const _ as *const c_void,
+                                 Some(manager_destroyed));
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return
+    };
+    let head_states = head_states();
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   state.managers.push(resource);
+                   state.announce(resource, &head_states);
+               });
+}
+
+unsafe extern "C" fn create_configuration(client: *mut wl_client,
+                                          manager: *mut wl_resource,
+                                          id: u32,
+                                          serial: u32) {
+    let resource = create_resource(client,
+                                   manager,
+                                   &zwlr_output_configuration_v1_interface,
+                                   id,
+                                   &CONFIGURATION_IMPLEMENTATION as *const _ as *const c_void,
+                                   Some(configuration_destroyed));
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return
+    };
+    STATE.with(|state| {
+                   state.borrow_mut()
+                        .configurations
+                        .push(Configuration { resource,
+                                              manager,
+                                              serial,
+                                              used: false,
+                                              enabled: Vec::new(),
+                                              disabled: Vec::new() })
+               });
+}
+
+unsafe extern "C" fn stop(_: *mut wl_client, manager: *mut wl_resource) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  manager,
+                  MANAGER_FINISHED_EVENT);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, manager);
+}
+
+/// Checks that a head can still be configured by the configuration, posting
+/// an error if it can't.
+fn check_configurable(configuration: &Configuration, head: *mut wl_resource) -> bool {
+    let error = if configuration.used {
+        Some((ALREADY_USED_ERROR, "configuration was already applied or tested"))
+    } else if configuration.enabled.iter().any(|enabled| enabled.head == head) ||
+              configuration.disabled.contains(&head)
+    {
+        Some((ALREADY_CONFIGURED_HEAD_ERROR, "head was already configured"))
+    } else {
+        None
+    };
+    match error {
+        Some((code, message)) => {
+            unsafe { post_error(configuration.resource, code, message) };
+            false
+        }
+        None => true
+    }
+}
+
+unsafe extern "C" fn enable_head(client: *mut wl_client,
+                                 configuration: *mut wl_resource,
+                                 id: u32,
+                                 head: *mut wl_resource) {
+    let current = STATE.with(|state| {
+        let state = state.borrow();
+        let configuration = state.configurations
+                                 .iter()
+                                 .find(|c| c.resource == configuration)?;
+        if !check_configurable(configuration, head) {
+            return None
+        }
+        state.heads
+             .iter()
+             .find(|h| h.resource == head)
+             .map(|h| h.current)
+    });
+    let current = match current {
+        Some(current) => current,
+        None => return
+    };
+    let resource = create_resource(client,
+                                   configuration,
+                                   &zwlr_output_configuration_head_v1_interface,
+                                   id,
+                                   &CONFIGURATION_HEAD_IMPLEMENTATION as *const _ as *const c_void,
+                                   None);
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return
+    };
+    // Anything the client doesn't set stays the way it is now.
+    let config = OutputConfig { enabled: true,
+                                ..current };
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if let Some(configuration) = state.configurations
+                                          .iter_mut()
+                                          .find(|c| c.resource == configuration)
+        {
+            configuration.enabled.push(ConfigurationHead { resource,
+                                                           head,
+                                                           config,
+                                                           set: Vec::new() });
+        }
+    });
+}
+
+unsafe extern "C" fn disable_head(_: *mut wl_client,
+                                  configuration: *mut wl_resource,
+                                  head: *mut wl_resource) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if let Some(configuration) = state.configurations
+                                          .iter_mut()
+                                          .find(|c| c.resource == configuration)
+        {
+            if check_configurable(configuration, head) {
+                configuration.disabled.push(head);
+            }
+        }
+    });
+}
+
+unsafe extern "C" fn apply(_: *mut wl_client, configuration: *mut wl_resource) {
+    apply_or_test(configuration, false)
+}
+
+unsafe extern "C" fn test(_: *mut wl_client, configuration: *mut wl_resource) {
+    apply_or_test(configuration, true)
+}
+
+unsafe fn apply_or_test(configuration: *mut wl_resource, test_only: bool) {
+    enum Outcome {
+        Error(u32, &'static str),
+        Cancelled,
+        Configs(Vec<(OutputHandle, OutputConfig)>)
+    }
+    let outcome = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let current_serial = state.serial;
+        let OutputManagement { ref heads,
+                               ref mut configurations,
+                               .. } = *state;
+        let configuration = configurations.iter_mut()
+                                          .find(|c| c.resource == configuration)?;
+        if configuration.used {
+            return Some(Outcome::Error(ALREADY_USED_ERROR,
+                                       "configuration was already applied or tested"))
+        }
+        configuration.used = true;
+        if configuration.serial != current_serial {
+            return Some(Outcome::Cancelled)
+        }
+        let mut configs = Vec::new();
+        for head in heads.iter().filter(|head| head.manager == configuration.manager) {
+            let enabled = configuration.enabled
+                                       .iter()
+                                       .find(|enabled| enabled.head == head.resource);
+            let config = match enabled {
+                Some(enabled) => enabled.config,
+                None if configuration.disabled.contains(&head.resource) => {
+                    OutputConfig { enabled: false,
+                                   ..head.current }
+                }
+                None => {
+                    return Some(Outcome::Error(UNCONFIGURED_HEAD_ERROR,
+                                               "every head must be enabled or disabled"))
+                }
+            };
+            configs.push((head.output.clone(), config));
+        }
+        Some(Outcome::Configs(configs))
+    });
+    let succeeded = match outcome {
+        None => return,
+        Some(Outcome::Error(code, message)) => {
+            post_error(configuration, code, message);
+            return
+        }
+        Some(Outcome::Cancelled) => {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          configuration,
+                          CANCELLED_EVENT);
+            return
+        }
+        Some(Outcome::Configs(configs)) if test_only => validate_output_configs(&configs),
+        Some(Outcome::Configs(configs)) => apply_output_configs(configs)
+    };
+    let event = if succeeded { SUCCEEDED_EVENT } else { FAILED_EVENT };
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  configuration,
+                  event);
+}
+
+/// Runs `f` on the configuration head of the resource, unless the property
+/// of the request was already set.
+unsafe fn with_configuration_head<F>(resource: *mut wl_resource, request: u32, f: F)
+    where F: FnOnce(&mut ConfigurationHead, &[Mode])
+{
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let OutputManagement { ref mut configurations,
+                               ref modes,
+                               .. } = *state;
+        let configuration_head = configurations.iter_mut()
+                                               .flat_map(|c| c.enabled.iter_mut())
+                                               .find(|head| head.resource == resource);
+        if let Some(configuration_head) = configuration_head {
+            if configuration_head.set.contains(&request) {
+                post_error(resource, ALREADY_SET_ERROR, "property was already set");
+                return
+            }
+            configuration_head.set.push(request);
+            f(configuration_head, modes)
+        }
+    })
+}
+
+unsafe extern "C" fn set_mode(_: *mut wl_client,
+                              resource: *mut wl_resource,
+                              mode: *mut wl_resource) {
+    with_configuration_head(resource, SET_MODE_REQUEST, |configuration_head, modes| {
+        match modes.iter().find(|m| m.resource == mode && m.head == configuration_head.head) {
+            Some(mode) => configuration_head.config.mode = Some(mode.mode),
+            None => post_error(resource, INVALID_MODE_ERROR, "mode doesn't belong to the head")
+        }
+    })
+}
+
+unsafe extern "C" fn set_custom_mode(_: *mut wl_client,
+                                     resource: *mut wl_resource,
+                                     width: i32,
+                                     height: i32,
+                                     refresh: i32) {
+    // A mode and a custom mode can't both be set.
+    with_configuration_head(resource, SET_MODE_REQUEST, |configuration_head, _| {
+        if width <= 0 || height <= 0 || refresh < 0 {
+            post_error(resource, INVALID_CUSTOM_MODE_ERROR, "invalid custom mode");
+            return
+        }
+        configuration_head.config.mode = Some(OutputModeConfig { width,
+                                                                 height,
+                                                                 refresh: from_mhz(refresh) })
+    })
+}
+
+unsafe extern "C" fn set_position(_: *mut wl_client, resource: *mut wl_resource, x: i32, y: i32) {
+    with_configuration_head(resource, SET_POSITION_REQUEST, |configuration_head, _| {
+        configuration_head.config.position = Some(Origin::new(x, y))
+    })
+}
+
+unsafe extern "C" fn set_transform(_: *mut wl_client, resource: *mut wl_resource, transform: i32) {
+    with_configuration_head(resource, SET_TRANSFORM_REQUEST, |configuration_head, _| {
+        match transform_from_int(transform) {
+            Some(transform) => configuration_head.config.transform = Some(transform),
+            None => post_error(resource, INVALID_TRANSFORM_ERROR, "invalid transform")
+        }
+    })
+}
+
+unsafe extern "C" fn set_scale(_: *mut wl_client, resource: *mut wl_resource, scale: i32) {
+    with_configuration_head(resource, SET_SCALE_REQUEST, |configuration_head, _| {
+        let scale = from_fixed(scale);
+        if scale <= 0.0 {
+            post_error(resource, INVALID_SCALE_ERROR, "scale must be positive");
+            return
+        }
+        configuration_head.config.scale = Some(scale)
+    })
+}
+
+unsafe extern "C" fn manager_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| {
+                   state.borrow_mut()
+                        .managers
+                        .retain(|&manager| manager != resource)
+               });
+}
+
+unsafe extern "C" fn head_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| state.borrow_mut().heads.retain(|head| head.resource != resource));
+}
+
+unsafe extern "C" fn mode_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| state.borrow_mut().modes.retain(|mode| mode.resource != resource));
+}
+
+unsafe extern "C" fn configuration_destroyed(resource: *mut wl_resource) {
+    let configuration = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let index = state.configurations
+                         .iter()
+                         .position(|c| c.resource == resource)?;
+        Some(state.configurations.remove(index))
+    });
+    // The configuration heads don't have a destructor, they go with it.
+    if let Some(configuration) = configuration {
+        for configuration_head in configuration.enabled {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_destroy,
+                          configuration_head.resource);
+        }
+    }
+}
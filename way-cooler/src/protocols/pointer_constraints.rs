@@ -0,0 +1,406 @@
+//! Implementation of `zwp_pointer_constraints_v1`, which lets clients like
+//! games and remote desktops lock the pointer in place or confine it to a
+//! region of their surface.
+//!
+//! A constraint takes hold once the client of its surface has the keyboard
+//! focus and the pointer moves inside its region while over the surface,
+//! and lets go when the client loses the keyboard focus or the pointer isn't
+//! over the region anymore. It does nothing anymore once the surface is
+//! destroyed. Only relative motion is constrained, absolute devices like
+//! tablets still move the cursor anywhere.
+
+use std::cell::RefCell;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use wayland_sys::server::*;
+use wlroots::wlroots_sys::{pixman_region32_rectangles, wlr_region_from_resource};
+use wlroots::{Area, Compositor, Origin, Size, SurfaceHandle};
+
+use super::interfaces::{zwp_confined_pointer_v1_interface, zwp_locked_pointer_v1_interface,
+                        zwp_pointer_constraints_v1_interface};
+use super::{bind_resource, create_global, create_resource, destroy_resource, post_error,
+            surface_resource};
+
+/// The highest version of the protocol that we support.
+const VERSION: u32 = 1;
+
+/// Opcodes of the `locked` and `unlocked` events of `zwp_locked_pointer_v1`,
+/// which are the same as the `confined` and `unconfined` events of
+/// `zwp_confined_pointer_v1`.
+const ACTIVATED_EVENT: u32 = 0;
+const DEACTIVATED_EVENT: u32 = 1;
+
+/// The `already_constrained` error of `zwp_pointer_constraints_v1`.
+const ALREADY_CONSTRAINED_ERROR: u32 = 1;
+
+/// The `oneshot` value of the `lifetime` enum of
+/// `zwp_pointer_constraints_v1`.
+const LIFETIME_ONESHOT: u32 = 1;
+
+thread_local! {
+    static CONSTRAINTS: RefCell<Vec<Constraint>> = RefCell::new(Vec::new());
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Kind {
+    Lock,
+    Confine
+}
+
+struct Constraint {
+    /// The `zwp_locked_pointer_v1` or `zwp_confined_pointer_v1` resource.
+    resource: *mut wl_resource,
+    kind: Kind,
+    /// The `wl_surface` resource of the constrained surface, null once it
+    /// was destroyed.
+    surface: *mut wl_resource,
+    /// Listens for the destruction of the surface.
+    ///
+    /// Boxed because libwayland keeps a pointer to it.
+    surface_destroyed: Box<wl_listener>,
+    /// Whether the constraint is gone once it lets go of the pointer.
+    oneshot: bool,
+    /// The rectangles of the region the pointer is kept in, in surface
+    /// coordinates. None if it's the whole surface.
+    region: Option<Vec<Area>>,
+    /// The region set by the client, which is applied on the next commit of
+    /// the surface.
+    pending_region: Option<Option<Vec<Area>>>,
+    /// Whether the constraint holds the pointer.
+    active: bool
+}
+
+#[repr(C)]
+struct ManagerImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    lock_pointer: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, *mut wl_resource,
+                                       *mut wl_resource, *mut wl_resource, u32),
+    confine_pointer: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32,
+                                          *mut wl_resource, *mut wl_resource,
+                                          *mut wl_resource, u32)
+}
+
+#[repr(C)]
+struct LockedPointerImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    set_cursor_position_hint: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32, i32),
+    set_region: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, *mut wl_resource)
+}
+
+#[repr(C)]
+struct ConfinedPointerImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    set_region: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, *mut wl_resource)
+}
+
+static MANAGER_IMPLEMENTATION: ManagerImplementation =
+    ManagerImplementation { destroy: destroy_resource,
+                            lock_pointer,
+                            confine_pointer };
+
+static LOCKED_POINTER_IMPLEMENTATION: LockedPointerImplementation =
+    LockedPointerImplementation { destroy: destroy_resource,
+                                  set_cursor_position_hint,
+                                  set_region };
+
+static CONFINED_POINTER_IMPLEMENTATION: ConfinedPointerImplementation =
+    ConfinedPointerImplementation { destroy: destroy_resource,
+                                    set_region };
+
+impl Constraint {
+    /// Whether a point of the surface, which has the given size, is in the
+    /// region.
+    fn contains(&self, (sx, sy): (f64, f64), size: Size) -> bool {
+        let in_area = |area: &Area| {
+            let Area { origin: Origin { x, y },
+                       size: Size { width, height } } = *area;
+            sx >= x as f64 && sy >= y as f64 && sx < (x + width) as f64 &&
+            sy < (y + height) as f64
+        };
+        in_area(&Area::new(Origin::new(0, 0), size)) &&
+        self.region.as_ref().map_or(true, |region| region.iter().any(in_area))
+    }
+
+    unsafe fn activate(&mut self) {
+        if !self.active {
+            self.active = true;
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          self.resource,
+                          ACTIVATED_EVENT);
+        }
+    }
+
+    unsafe fn deactivate(&mut self) {
+        if self.active {
+            self.active = false;
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          self.resource,
+                          DEACTIVATED_EVENT);
+        }
+    }
+
+    /// Lets go of the pointer. A oneshot constraint does nothing anymore
+    /// afterwards, the client has to ask again to constrain the pointer.
+    unsafe fn release(&mut self) {
+        if self.active && self.oneshot {
+            self.disable();
+        } else {
+            self.deactivate();
+        }
+    }
+
+    /// Lets go of the pointer and stops listening for the destruction of the
+    /// surface, after which the constraint does nothing anymore.
+    ///
+    /// This is the teardown of both a constraint that is destroyed and one
+    /// that is gone for good while its resource lives on.
+    unsafe fn disable(&mut self) {
+        self.deactivate();
+        if !self.surface.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut self.surface_destroyed.link);
+            self.surface = ptr::null_mut();
+        }
+    }
+}
+
+/// The client of a resource.
+unsafe fn client(resource: *mut wl_resource) -> *mut wl_client {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, resource)
+}
+
+/// Advertises the pointer constraints global on the compositor's display.
+pub fn init(compositor: &mut Compositor) {
+    create_global(compositor.display as *mut wl_display,
+                  unsafe { &zwp_pointer_constraints_v1_interface },
+                  VERSION,
+                  bind,
+                  "pointer constraints");
+}
+
+/// Applies the regions that were set since the last commit of a surface.
+pub fn surface_committed(surface: &SurfaceHandle) {
+    let surface = match surface_resource(surface) {
+        Some(surface) => surface,
+        None => return
+    };
+    CONSTRAINTS.with(|constraints| {
+        let mut constraints = constraints.borrow_mut();
+        for constraint in constraints.iter_mut().filter(|c| c.surface == surface) {
+            if let Some(region) = constraint.pending_region.take() {
+                constraint.region = region;
+            }
+        }
+    });
+}
+
+/// Lets go of the pointer if the client of the constraint that holds it
+/// doesn't have the keyboard focus anymore.
+pub fn focus_changed(focused: Option<&SurfaceHandle>) {
+    let focused = focused.and_then(surface_resource).map(|surface| unsafe { client(surface) });
+    CONSTRAINTS.with(|constraints| {
+        let mut constraints = constraints.borrow_mut();
+        for constraint in constraints.iter_mut().filter(|c| c.active) {
+            if Some(unsafe { client(constraint.surface) }) != focused {
+                unsafe { constraint.release() };
+            }
+        }
+    });
+}
+
+/// Constrains relative motion of the pointer, which is at `(sx, sy)` in
+/// the coordinates of `under_cursor` if it's over a surface. `focused` is
+/// the surface of the view with the keyboard focus.
+///
+/// The constraint of the surface under the cursor takes hold if its client
+/// has the keyboard focus and the pointer is in its region, the others let
+/// go. Returns how far the pointer can move.
+pub fn constrain_motion(under_cursor: Option<(&SurfaceHandle, f64, f64)>,
+                        focused: Option<&SurfaceHandle>,
+                        (dx, dy): (f64, f64))
+                        -> (f64, f64) {
+    let focused = focused.and_then(surface_resource).map(|surface| unsafe { client(surface) });
+    let under_cursor = under_cursor.and_then(|(surface, sx, sy)| {
+        let size = with_handles!([(surface: {surface})] => {
+            let (width, height) = surface.current_state().size();
+            Size::new(width, height)
+        }).ok()?;
+        let resource = surface_resource(surface)?;
+        if Some(unsafe { client(resource) }) != focused {
+            return None
+        }
+        Some((resource, (sx, sy), size))
+    });
+    CONSTRAINTS.with(|constraints| {
+        let mut constraints = constraints.borrow_mut();
+        let mut motion = (dx, dy);
+        for constraint in constraints.iter_mut() {
+            let (sx, sy, size) = match under_cursor {
+                Some((surface, (sx, sy), size)) if constraint.surface == surface &&
+                                                   constraint.contains((sx, sy), size) => {
+                    (sx, sy, size)
+                }
+                _ => {
+                    unsafe { constraint.release() };
+                    continue
+                }
+            };
+            unsafe { constraint.activate() };
+            motion = match constraint.kind {
+                Kind::Lock => (0.0, 0.0),
+                Kind::Confine => {
+                    // Slide along the edge when only one direction leaves it.
+                    let moves = [(dx, dy), (dx, 0.0), (0.0, dy)];
+                    moves.iter()
+                         .cloned()
+                         .find(|&(dx, dy)| constraint.contains((sx + dx, sy + dy), size))
+                         .unwrap_or((0.0, 0.0))
+                }
+            };
+        }
+        motion
+    })
+}
+
+/// The rectangles of a `wl_region`, or None if there is none, which means
+/// the whole surface.
+unsafe fn region_areas(region: *mut wl_resource) -> Option<Vec<Area>> {
+    if region.is_null() {
+        return None
+    }
+    let region = wlr_region_from_resource(region as *mut _);
+    let mut count: c_int = 0;
+    let boxes = pixman_region32_rectangles(region, &mut count);
+    let mut areas = Vec::with_capacity(count as usize);
+    for index in 0..count as isize {
+        let rect = &*boxes.offset(index);
+        areas.push(Area::new(Origin::new(rect.x1, rect.y1),
+                             Size::new(rect.x2 - rect.x1, rect.y2 - rect.y1)));
+    }
+    Some(areas)
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, _: *mut c_void, version: u32, id: u32) {
+    bind_resource(client,
+                  &zwp_pointer_constraints_v1_interface,
+                  version,
+                  id,
+                  &MANAGER_IMPLEMENTATION as *const _ as *const c_void,
+                  None);
+}
+
+unsafe extern "C" fn lock_pointer(client: *mut wl_client,
+                                  manager: *mut wl_resource,
+                                  id: u32,
+                                  surface: *mut wl_resource,
+                                  _: *mut wl_resource,
+                                  region: *mut wl_resource,
+                                  lifetime: u32) {
+    create_constraint(client, manager, id, surface, region, lifetime, Kind::Lock);
+}
+
+unsafe extern "C" fn confine_pointer(client: *mut wl_client,
+                                     manager: *mut wl_resource,
+                                     id: u32,
+                                     surface: *mut wl_resource,
+                                     _: *mut wl_resource,
+                                     region: *mut wl_resource,
+                                     lifetime: u32) {
+    create_constraint(client, manager, id, surface, region, lifetime, Kind::Confine);
+}
+
+unsafe fn create_constraint(client: *mut wl_client,
+                            manager: *mut wl_resource,
+                            id: u32,
+                            surface: *mut wl_resource,
+                            region: *mut wl_resource,
+                            lifetime: u32,
+                            kind: Kind) {
+    // There is only one seat, so every constraint is on its pointer.
+    let constrained =
+        CONSTRAINTS.with(|constraints| constraints.borrow().iter().any(|c| c.surface == surface));
+    if constrained {
+        post_error(manager,
+                   ALREADY_CONSTRAINED_ERROR,
+                   "the surface already constrains the pointer");
+        return
+    }
+    let (interface, implementation) = match kind {
+        Kind::Lock => (&zwp_locked_pointer_v1_interface,
+                       &LOCKED_POINTER_IMPLEMENTATION as *const _ as *const c_void),
+        Kind::Confine => (&zwp_confined_pointer_v1_interface,
+                          &CONFINED_POINTER_IMPLEMENTATION as *const _ as *const c_void)
+    };
+    let resource = create_resource(client,
+                                   manager,
+                                   interface,
+                                   id,
+                                   implementation,
+                                   Some(constraint_destroyed));
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return
+    };
+    let mut listener = Box::new(wl_listener { link: mem::zeroed(),
+                                              notify: surface_destroyed });
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_add_destroy_listener,
+                  surface,
+                  &mut *listener);
+    let constraint = Constraint { resource,
+                                  kind,
+                                  surface,
+                                  surface_destroyed: listener,
+                                  oneshot: lifetime == LIFETIME_ONESHOT,
+                                  region: region_areas(region),
+                                  pending_region: None,
+                                  active: false };
+    CONSTRAINTS.with(|constraints| constraints.borrow_mut().push(constraint));
+}
+
+unsafe extern "C" fn set_cursor_position_hint(_: *mut wl_client,
+                                              _: *mut wl_resource,
+                                              _: i32,
+                                              _: i32) {
+    // The cursor stays where it was locked, which the protocol allows.
+}
+
+unsafe extern "C" fn set_region(_: *mut wl_client,
+                                resource: *mut wl_resource,
+                                region: *mut wl_resource) {
+    let region = region_areas(region);
+    CONSTRAINTS.with(|constraints| {
+        let mut constraints = constraints.borrow_mut();
+        if let Some(constraint) = constraints.iter_mut().find(|c| c.resource == resource) {
+            constraint.pending_region = Some(region);
+        }
+    });
+}
+
+unsafe extern "C" fn constraint_destroyed(resource: *mut wl_resource) {
+    CONSTRAINTS.with(|constraints| {
+        let mut constraints = constraints.borrow_mut();
+        if let Some(index) = constraints.iter().position(|c| c.resource == resource) {
+            // The client ignores the events of the object it destroyed.
+            constraints.remove(index).disable();
+        }
+    });
+}
+
+/// Makes the constraint of a destroyed surface inert, until the client
+/// destroys it.
+unsafe extern "C" fn surface_destroyed(listener: *mut wl_listener, _: *mut c_void) {
+    CONSTRAINTS.with(|constraints| {
+        let mut constraints = constraints.borrow_mut();
+        let destroyed = constraints.iter_mut()
+                                   .find(|c| &*c.surface_destroyed as *const _ == listener);
+        if let Some(constraint) = destroyed {
+            constraint.disable();
+        }
+    });
+}
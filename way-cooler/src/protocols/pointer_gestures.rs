@@ -0,0 +1,270 @@
+//! Implementation of `zwp_pointer_gestures_v1`, which sends the swipes and
+//! pinches of touchpads to the surface under the cursor.
+//!
+//! A gesture goes to the surface it began on until it ends, even if the
+//! cursor leaves it.
+
+use std::cell::RefCell;
+use std::os::raw::c_void;
+use std::ptr;
+
+use wayland_sys::server::*;
+use wlroots::{Compositor, SurfaceHandle};
+
+use super::interfaces::{zwp_pointer_gesture_pinch_v1_interface,
+                        zwp_pointer_gesture_swipe_v1_interface,
+                        zwp_pointer_gestures_v1_interface};
+use super::{bind_resource, create_global, create_resource, destroy_resource, surface_resource,
+            to_fixed};
+
+/// The highest version of the protocol that we support.
+///
+/// Version 3 adds hold gestures, which wlroots doesn't report.
+const VERSION: u32 = 2;
+
+/// Opcodes of the events of `zwp_pointer_gesture_swipe_v1` and
+/// `zwp_pointer_gesture_pinch_v1`.
+const BEGIN_EVENT: u32 = 0;
+const UPDATE_EVENT: u32 = 1;
+const END_EVENT: u32 = 2;
+
+thread_local! {
+    static STATE: RefCell<PointerGestures> = RefCell::new(PointerGestures::default());
+}
+
+/// The kinds of gestures of a touchpad.
+///
+/// The values are the ones the window management protocol uses.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Gesture {
+    Swipe = 0,
+    Pinch = 1
+}
+
+#[derive(Debug)]
+struct PointerGestures {
+    display: *mut wl_display,
+    /// All the `zwp_pointer_gesture_swipe_v1` resources.
+    swipes: Vec<*mut wl_resource>,
+    /// All the `zwp_pointer_gesture_pinch_v1` resources.
+    pinches: Vec<*mut wl_resource>,
+    /// The resources that got the begin event of the swipe in progress.
+    swipe: Option<Vec<*mut wl_resource>>,
+    /// The resources that got the begin event of the pinch in progress.
+    pinch: Option<Vec<*mut wl_resource>>
+}
+
+#[repr(C)]
+struct ManagerImplementation {
+    get_swipe_gesture: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32,
+                                            *mut wl_resource),
+    get_pinch_gesture: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32,
+                                            *mut wl_resource),
+    release: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+#[repr(C)]
+struct GestureImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+static MANAGER_IMPLEMENTATION: ManagerImplementation =
+    ManagerImplementation { get_swipe_gesture,
+                            get_pinch_gesture,
+                            release: destroy_resource };
+
+static GESTURE_IMPLEMENTATION: GestureImplementation =
+    GestureImplementation { destroy: destroy_resource };
+
+impl Default for PointerGestures {
+    fn default() -> Self {
+        PointerGestures { display: ptr::null_mut(),
+                          swipes: Vec::new(),
+                          pinches: Vec::new(),
+                          swipe: None,
+                          pinch: None }
+    }
+}
+
+impl PointerGestures {
+    /// All the resources of a kind of gesture, and the ones that are in the
+    /// gesture in progress.
+    fn gesture_mut(&mut self,
+                   gesture: Gesture)
+                   -> (&mut Vec<*mut wl_resource>, &mut Option<Vec<*mut wl_resource>>) {
+        match gesture {
+            Gesture::Swipe => (&mut self.swipes, &mut self.swipe),
+            Gesture::Pinch => (&mut self.pinches, &mut self.pinch)
+        }
+    }
+}
+
+/// Advertises the pointer gestures global on the compositor's display.
+pub fn init(compositor: &mut Compositor) {
+    let display = compositor.display as *mut wl_display;
+    STATE.with(|state| state.borrow_mut().display = display);
+    create_global(display,
+                  unsafe { &zwp_pointer_gestures_v1_interface },
+                  VERSION,
+                  bind,
+                  "pointer gestures");
+}
+
+/// Begins a gesture on the surface under the cursor.
+///
+/// A gesture that was still in progress is cancelled first.
+pub fn begin(gesture: Gesture, surface: Option<SurfaceHandle>, fingers: u32, time_msec: u32) {
+    end(gesture, true, time_msec);
+    let surface_resource = surface.and_then(|surface| surface_resource(&surface));
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let display = state.display;
+        let (resources, current) = state.gesture_mut(gesture);
+        let mut began = Vec::new();
+        if let Some(surface_resource) = surface_resource {
+            unsafe {
+                let client =
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, surface_resource);
+                let serial = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_next_serial, display);
+                for &resource in resources.iter() {
+                    if ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, resource) !=
+                       client
+                    {
+                        continue
+                    }
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                  wl_resource_post_event,
+                                  resource,
+                                  BEGIN_EVENT,
+                                  serial,
+                                  time_msec,
+                                  surface_resource,
+                                  fingers);
+                    began.push(resource);
+                }
+            }
+        }
+        *current = Some(began);
+    });
+}
+
+/// Sends how the gesture in progress moved, in surface coordinates.
+///
+/// The scale and rotation are only sent for pinches, the scale being
+/// relative to the start of the pinch and the rotation in degrees clockwise
+/// since the last update.
+pub fn update(gesture: Gesture, (dx, dy): (f64, f64), scale: f64, rotation: f64, time_msec: u32) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let (_, current) = state.gesture_mut(gesture);
+        for &resource in current.iter().flat_map(|current| current) {
+            unsafe {
+                match gesture {
+                    Gesture::Swipe => {
+                        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                      wl_resource_post_event,
+                                      resource,
+                                      UPDATE_EVENT,
+                                      time_msec,
+                                      to_fixed(dx),
+                                      to_fixed(dy))
+                    }
+                    Gesture::Pinch => {
+                        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                      wl_resource_post_event,
+                                      resource,
+                                      UPDATE_EVENT,
+                                      time_msec,
+                                      to_fixed(dx),
+                                      to_fixed(dy),
+                                      to_fixed(scale),
+                                      to_fixed(rotation))
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Ends the gesture in progress, if there is one.
+pub fn end(gesture: Gesture, cancelled: bool, time_msec: u32) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let display = state.display;
+        let (_, current) = state.gesture_mut(gesture);
+        let resources = match current.take() {
+            Some(resources) => resources,
+            None => return
+        };
+        for resource in resources {
+            unsafe {
+                let serial = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_next_serial, display);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              resource,
+                              END_EVENT,
+                              serial,
+                              time_msec,
+                              cancelled as i32);
+            }
+        }
+    });
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, _: *mut c_void, version: u32, id: u32) {
+    bind_resource(client,
+                  &zwp_pointer_gestures_v1_interface,
+                  version,
+                  id,
+                  &MANAGER_IMPLEMENTATION as *const _ as *const c_void,
+                  None);
+}
+
+unsafe extern "C" fn get_swipe_gesture(client: *mut wl_client,
+                                       manager: *mut wl_resource,
+                                       id: u32,
+                                       _: *mut wl_resource) {
+    create_gesture(client, manager, id, Gesture::Swipe);
+}
+
+unsafe extern "C" fn get_pinch_gesture(client: *mut wl_client,
+                                       manager: *mut wl_resource,
+                                       id: u32,
+                                       _: *mut wl_resource) {
+    create_gesture(client, manager, id, Gesture::Pinch);
+}
+
+unsafe fn create_gesture(client: *mut wl_client,
+                         manager: *mut wl_resource,
+                         id: u32,
+                         gesture: Gesture) {
+    // There is only one seat, so every gesture comes from its pointer.
+    let interface = match gesture {
+        Gesture::Swipe => &zwp_pointer_gesture_swipe_v1_interface,
+        Gesture::Pinch => &zwp_pointer_gesture_pinch_v1_interface
+    };
+    let resource = create_resource(client,
+                                   manager,
+                                   interface,
+                                   id,
+                                   &GESTURE_IMPLEMENTATION as *const _ as *const c_void,
+                                   Some(gesture_destroyed));
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return
+    };
+    STATE.with(|state| state.borrow_mut().gesture_mut(gesture).0.push(resource));
+}
+
+unsafe extern "C" fn gesture_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        for &gesture in &[Gesture::Swipe, Gesture::Pinch] {
+            let (resources, current) = state.gesture_mut(gesture);
+            resources.retain(|&other| other != resource);
+            if let Some(ref mut current) = *current {
+                current.retain(|&other| other != resource);
+            }
+        }
+    });
+}
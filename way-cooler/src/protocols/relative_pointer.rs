@@ -0,0 +1,113 @@
+//! Implementation of `zwp_relative_pointer_manager_v1`, which sends how the
+//! pointer moved to clients like games, even while it is locked in place by
+//! `pointer_constraints`.
+//!
+//! The version of wlroots we use only reports accelerated motion, so it is
+//! also sent as the unaccelerated motion.
+
+use std::cell::RefCell;
+use std::os::raw::c_void;
+
+use wayland_sys::server::*;
+use wlroots::{Compositor, SurfaceHandle};
+
+use super::interfaces::{zwp_relative_pointer_manager_v1_interface,
+                        zwp_relative_pointer_v1_interface};
+use super::{bind_resource, create_global, create_resource, destroy_resource, surface_resource,
+            to_fixed};
+
+/// The highest version of the protocol that we support.
+const VERSION: u32 = 1;
+
+/// Opcode of the `relative_motion` event of `zwp_relative_pointer_v1`.
+const RELATIVE_MOTION_EVENT: u32 = 0;
+
+thread_local! {
+    /// All the `zwp_relative_pointer_v1` resources.
+    static POINTERS: RefCell<Vec<*mut wl_resource>> = RefCell::new(Vec::new());
+}
+
+#[repr(C)]
+struct ManagerImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    get_relative_pointer: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32,
+                                               *mut wl_resource)
+}
+
+#[repr(C)]
+struct PointerImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+static MANAGER_IMPLEMENTATION: ManagerImplementation =
+    ManagerImplementation { destroy: destroy_resource,
+                            get_relative_pointer };
+
+static POINTER_IMPLEMENTATION: PointerImplementation =
+    PointerImplementation { destroy: destroy_resource };
+
+/// Advertises the relative pointer manager global on the compositor's
+/// display.
+pub fn init(compositor: &mut Compositor) {
+    create_global(compositor.display as *mut wl_display,
+                  unsafe { &zwp_relative_pointer_manager_v1_interface },
+                  VERSION,
+                  bind,
+                  "relative pointer manager");
+}
+
+/// Sends how the pointer moved to the client of the surface under it.
+pub fn relative_motion(surface: Option<SurfaceHandle>, (dx, dy): (f64, f64), time_msec: u32) {
+    let surface_resource = match surface.and_then(|surface| surface_resource(&surface)) {
+        Some(surface_resource) => surface_resource,
+        None => return
+    };
+    let utime = time_msec as u64 * 1000;
+    POINTERS.with(|pointers| unsafe {
+        let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, surface_resource);
+        for &pointer in pointers.borrow().iter() {
+            if ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, pointer) != client {
+                continue
+            }
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          pointer,
+                          RELATIVE_MOTION_EVENT,
+                          (utime >> 32) as u32,
+                          utime as u32,
+                          to_fixed(dx),
+                          to_fixed(dy),
+                          to_fixed(dx),
+                          to_fixed(dy));
+        }
+    });
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, _: *mut c_void, version: u32, id: u32) {
+    bind_resource(client,
+                  &zwp_relative_pointer_manager_v1_interface,
+                  version,
+                  id,
+                  &MANAGER_IMPLEMENTATION as *const _ as *const c_void,
+                  None);
+}
+
+unsafe extern "C" fn get_relative_pointer(client: *mut wl_client,
+                                          manager: *mut wl_resource,
+                                          id: u32,
+                                          _: *mut wl_resource) {
+    // There is only one seat, so every relative pointer follows its pointer.
+    let pointer = create_resource(client,
+                                  manager,
+                                  &zwp_relative_pointer_v1_interface,
+                                  id,
+                                  &POINTER_IMPLEMENTATION as *const _ as *const c_void,
+                                  Some(pointer_destroyed));
+    if let Some(pointer) = pointer {
+        POINTERS.with(|pointers| pointers.borrow_mut().push(pointer));
+    }
+}
+
+unsafe extern "C" fn pointer_destroyed(resource: *mut wl_resource) {
+    POINTERS.with(|pointers| pointers.borrow_mut().retain(|&pointer| pointer != resource));
+}
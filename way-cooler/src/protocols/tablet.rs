@@ -0,0 +1,920 @@
+//! Implementation of `zwp_tablet_manager_v2`, which sends the events of
+//! drawing tablets to the clients that support them.
+//!
+//! The version of wlroots we use can't tell the tools of a tablet apart, so
+//! every tablet is advertised with a single pen that stands for whatever
+//! tool is used on it. Pads have one group with all of their buttons, their
+//! rings and strips aren't advertised.
+//!
+//! Tools only send tablet events to clients that bound a tablet seat.
+//! Everywhere else they drive the cursor like a pointer, see
+//! `input::tablet`.
+
+use std::cell::RefCell;
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+
+use wayland_sys::common::wl_array;
+use wayland_sys::server::*;
+use wlroots::utils::current_time;
+use wlroots::wlroots_sys::{wlr_cursor_set_surface, wlr_surface_from_resource};
+use wlroots::{self, Compositor, SurfaceHandle, TabletPad, TabletPadHandle, TabletTool,
+              TabletToolHandle};
+
+use super::interfaces::{zwp_tablet_manager_v2_interface, zwp_tablet_pad_group_v2_interface,
+                        zwp_tablet_pad_v2_interface, zwp_tablet_seat_v2_interface,
+                        zwp_tablet_tool_v2_interface, zwp_tablet_v2_interface};
+use super::{bind_resource, create_global, create_resource, destroy_resource, send_string,
+            surface_resource, to_fixed};
+
+/// The highest version of the protocol that we support.
+const VERSION: u32 = 1;
+
+/// Opcodes of the events of `zwp_tablet_seat_v2`.
+const TABLET_ADDED_EVENT: u32 = 0;
+const TOOL_ADDED_EVENT: u32 = 1;
+const PAD_ADDED_EVENT: u32 = 2;
+
+/// Opcodes of the events of `zwp_tablet_tool_v2`.
+const TYPE_EVENT: u32 = 0;
+const CAPABILITY_EVENT: u32 = 3;
+const TOOL_DONE_EVENT: u32 = 4;
+const TOOL_REMOVED_EVENT: u32 = 5;
+const PROXIMITY_IN_EVENT: u32 = 6;
+const PROXIMITY_OUT_EVENT: u32 = 7;
+const DOWN_EVENT: u32 = 8;
+const UP_EVENT: u32 = 9;
+const MOTION_EVENT: u32 = 10;
+const PRESSURE_EVENT: u32 = 11;
+const DISTANCE_EVENT: u32 = 12;
+const TILT_EVENT: u32 = 13;
+const TOOL_BUTTON_EVENT: u32 = 17;
+const FRAME_EVENT: u32 = 18;
+
+/// Opcodes of the events of `zwp_tablet_v2`.
+const NAME_EVENT: u32 = 0;
+const ID_EVENT: u32 = 1;
+const TABLET_DONE_EVENT: u32 = 3;
+const TABLET_REMOVED_EVENT: u32 = 4;
+
+/// Opcodes of the events of `zwp_tablet_pad_group_v2`.
+const GROUP_BUTTONS_EVENT: u32 = 0;
+const GROUP_DONE_EVENT: u32 = 4;
+
+/// Opcodes of the events of `zwp_tablet_pad_v2`.
+const GROUP_EVENT: u32 = 0;
+const PAD_BUTTONS_EVENT: u32 = 2;
+const PAD_DONE_EVENT: u32 = 3;
+const PAD_BUTTON_EVENT: u32 = 4;
+const ENTER_EVENT: u32 = 5;
+const LEAVE_EVENT: u32 = 6;
+const PAD_REMOVED_EVENT: u32 = 7;
+
+/// The `pen` value of the `type` enum of `zwp_tablet_tool_v2`.
+const TYPE_PEN: u32 = 0x140;
+
+/// Values of the `capability` enum of `zwp_tablet_tool_v2`.
+const CAPABILITY_TILT: u32 = 1;
+const CAPABILITY_PRESSURE: u32 = 2;
+const CAPABILITY_DISTANCE: u32 = 3;
+
+/// Values of the `button_state` enums of `zwp_tablet_tool_v2` and
+/// `zwp_tablet_pad_v2`.
+const BUTTON_RELEASED: u32 = 0;
+const BUTTON_PRESSED: u32 = 1;
+
+thread_local! {
+    static STATE: RefCell<Tablets> = RefCell::new(Tablets::default());
+}
+
+/// The axes of a tool, other than its position.
+///
+/// Pressure and distance are normalized between 0 and 1, tilt is in degrees.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ToolAxes {
+    pub pressure: Option<f64>,
+    pub distance: Option<f64>,
+    pub tilt_x: Option<f64>,
+    pub tilt_y: Option<f64>
+}
+
+#[derive(Debug)]
+struct Tablets {
+    display: *mut wl_display,
+    /// All the `zwp_tablet_seat_v2` resources.
+    seats: Vec<*mut wl_resource>,
+    tablets: Vec<Tablet>,
+    pads: Vec<Pad>
+}
+
+/// A tablet, and the pen that is used on it.
+#[derive(Debug)]
+struct Tablet {
+    device: TabletToolHandle,
+    name: String,
+    vendor: u32,
+    product: u32,
+    /// The resources of the tablet and its pen, one of each per tablet seat.
+    resources: Vec<SeatResources>,
+    /// The surface the pen is in proximity of.
+    focus: Option<ToolFocus>,
+    /// Whether the pen touches the tablet.
+    down: bool,
+    /// The buttons of the pen that are held down on the focused surface.
+    buttons: Vec<u32>,
+    /// The latest value of each axis.
+    axes: ToolAxes
+}
+
+/// The `zwp_tablet_v2` and `zwp_tablet_tool_v2` resources of a tablet that
+/// were sent to a tablet seat.
+///
+/// Either is null once the client destroyed it.
+#[derive(Debug)]
+struct SeatResources {
+    tablet: *mut wl_resource,
+    tool: *mut wl_resource
+}
+
+#[derive(Debug)]
+struct ToolFocus {
+    surface: SurfaceHandle,
+    /// The `zwp_tablet_tool_v2` resource of the client of the surface.
+    tool: *mut wl_resource,
+    /// The serial of the `proximity_in` event, which `set_cursor` must use.
+    serial: u32,
+    /// Where the surface was in the layout when the pen entered it.
+    origin: (f64, f64)
+}
+
+/// A pad, with all of its buttons in one group.
+#[derive(Debug)]
+struct Pad {
+    device: TabletPadHandle,
+    buttons: u32,
+    /// The `zwp_tablet_pad_v2` resources of the pad, one per tablet seat.
+    resources: Vec<*mut wl_resource>,
+    /// The surface that gets the events of the pad.
+    focus: Option<PadFocus>
+}
+
+#[derive(Debug)]
+struct PadFocus {
+    surface: SurfaceHandle,
+    /// The `zwp_tablet_pad_v2` resource of the client of the surface.
+    pad: *mut wl_resource
+}
+
+#[repr(C)]
+struct ManagerImplementation {
+    get_tablet_seat: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, *mut wl_resource),
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+#[repr(C)]
+struct DestroyImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+#[repr(C)]
+struct ToolImplementation {
+    set_cursor: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, *mut wl_resource,
+                                     i32, i32),
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+#[repr(C)]
+struct PadImplementation {
+    set_feedback: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, *const c_char,
+                                       u32),
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+static MANAGER_IMPLEMENTATION: ManagerImplementation =
+    ManagerImplementation { get_tablet_seat,
+                            destroy: destroy_resource };
+
+/// The implementation of the interfaces that only have a destructor.
+static DESTROY_IMPLEMENTATION: DestroyImplementation =
+    DestroyImplementation { destroy: destroy_resource };
+
+static TOOL_IMPLEMENTATION: ToolImplementation =
+    ToolImplementation { set_cursor,
+                         destroy: destroy_resource };
+
+static PAD_IMPLEMENTATION: PadImplementation =
+    PadImplementation { set_feedback,
+                        destroy: destroy_resource };
+
+impl Default for Tablets {
+    fn default() -> Self {
+        Tablets { display: ptr::null_mut(),
+                  seats: Vec::new(),
+                  tablets: Vec::new(),
+                  pads: Vec::new() }
+    }
+}
+
+impl ToolAxes {
+    /// Takes the axes that are set in `other`.
+    fn update(&mut self, other: &ToolAxes) {
+        self.pressure = other.pressure.or(self.pressure);
+        self.distance = other.distance.or(self.distance);
+        self.tilt_x = other.tilt_x.or(self.tilt_x);
+        self.tilt_y = other.tilt_y.or(self.tilt_y);
+    }
+}
+
+impl Tablet {
+    /// The resources of the tablet that belong to a client, if it bound a
+    /// tablet seat and still has them.
+    unsafe fn resources_of(&self, client: *mut wl_client) -> Option<&SeatResources> {
+        self.resources.iter().find(|resources| {
+            !resources.tablet.is_null() && !resources.tool.is_null() &&
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, resources.tool) == client
+        })
+    }
+
+    /// Brings the pen in proximity of a surface, if its client can use it.
+    unsafe fn enter(&mut self,
+                    display: *mut wl_display,
+                    surface: SurfaceHandle,
+                    origin: (f64, f64))
+                    -> bool {
+        let surface_resource = match surface_resource(&surface) {
+            Some(surface_resource) => surface_resource,
+            None => return false
+        };
+        let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, surface_resource);
+        let (tablet, tool) = match self.resources_of(client) {
+            Some(resources) => (resources.tablet, resources.tool),
+            None => return false
+        };
+        let serial = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_next_serial, display);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      tool,
+                      PROXIMITY_IN_EVENT,
+                      serial,
+                      tablet,
+                      surface_resource);
+        self.focus = Some(ToolFocus { surface,
+                                      tool,
+                                      serial,
+                                      origin });
+        true
+    }
+
+    /// Takes the pen away from the surface it is in proximity of, releasing
+    /// what it holds down.
+    unsafe fn leave(&mut self, display: *mut wl_display, time_msec: u32) {
+        if let Some(focus) = self.focus.take() {
+            if self.down {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              focus.tool,
+                              UP_EVENT);
+            }
+            for button in self.buttons.drain(..) {
+                let serial = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_next_serial, display);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              focus.tool,
+                              TOOL_BUTTON_EVENT,
+                              serial,
+                              button,
+                              BUTTON_RELEASED);
+            }
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          focus.tool,
+                          PROXIMITY_OUT_EVENT);
+            send_frame(focus.tool, time_msec);
+        }
+        self.down = false;
+    }
+}
+
+/// Advertises the tablet manager global on the compositor's display.
+pub fn init(compositor: &mut Compositor) {
+    let display = compositor.display as *mut wl_display;
+    STATE.with(|state| state.borrow_mut().display = display);
+    create_global(display,
+                  unsafe { &zwp_tablet_manager_v2_interface },
+                  VERSION,
+                  bind,
+                  "tablet manager");
+}
+
+/// Advertises a new tablet to every tablet seat.
+pub fn tablet_added(device: &mut TabletTool) {
+    let input_device = device.input_device();
+    let mut tablet = Tablet { device: device.weak_reference(),
+                              name: input_device.name(),
+                              vendor: input_device.vendor(),
+                              product: input_device.product(),
+                              resources: Vec::new(),
+                              focus: None,
+                              down: false,
+                              buttons: Vec::new(),
+                              axes: ToolAxes::default() };
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   for &seat in &state.seats {
+                       unsafe { announce_tablet(seat, &mut tablet) };
+                   }
+                   state.tablets.push(tablet);
+               });
+}
+
+/// Tells the tablet seats that a tablet and its pen are gone.
+pub fn tablet_removed(device: &TabletToolHandle) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let display = state.display;
+        let index = match state.tablets.iter().position(|tablet| tablet.device == *device) {
+            Some(index) => index,
+            None => return
+        };
+        let mut tablet = state.tablets.remove(index);
+        unsafe {
+            tablet.leave(display, current_time_msec());
+            for resources in &tablet.resources {
+                if !resources.tool.is_null() {
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                  wl_resource_post_event,
+                                  resources.tool,
+                                  TOOL_REMOVED_EVENT);
+                }
+                if !resources.tablet.is_null() {
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                  wl_resource_post_event,
+                                  resources.tablet,
+                                  TABLET_REMOVED_EVENT);
+                }
+            }
+        }
+    });
+}
+
+/// Advertises a new pad to every tablet seat.
+pub fn pad_added(device: &mut TabletPad) {
+    let mut pad = Pad { device: device.weak_reference(),
+                        buttons: device.button_count() as u32,
+                        resources: Vec::new(),
+                        focus: None };
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   for &seat in &state.seats {
+                       unsafe { announce_pad(seat, &mut pad) };
+                   }
+                   state.pads.push(pad);
+               });
+}
+
+/// Tells the tablet seats that a pad is gone.
+pub fn pad_removed(device: &TabletPadHandle) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let index = match state.pads.iter().position(|pad| pad.device == *device) {
+            Some(index) => index,
+            None => return
+        };
+        let pad = state.pads.remove(index);
+        for &resource in &pad.resources {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              resource,
+                              PAD_REMOVED_EVENT);
+            }
+        }
+    });
+}
+
+/// Whether the pen of a tablet is in proximity of a surface of a client
+/// that uses tablets.
+pub fn has_focus(device: &TabletToolHandle) -> bool {
+    STATE.with(|state| {
+                   state.borrow()
+                        .tablets
+                        .iter()
+                        .any(|tablet| tablet.device == *device && tablet.focus.is_some())
+               })
+}
+
+/// Moves the pen of a tablet over the surface at a point of the layout, and
+/// sends the axes that changed.
+///
+/// While the pen touches the tablet it stays on the surface it went down on.
+/// Returns false if the pen isn't on a surface of a client that uses
+/// tablets, in which case it was taken out of proximity.
+pub fn tool_motion(device: &TabletToolHandle,
+                   target: Option<(SurfaceHandle, f64, f64)>,
+                   (lx, ly): (f64, f64),
+                   axes: &ToolAxes,
+                   time_msec: u32)
+                   -> bool {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let display = state.display;
+        let tablet = match state.tablets.iter_mut().find(|tablet| tablet.device == *device) {
+            Some(tablet) => tablet,
+            None => return false
+        };
+        tablet.axes.update(axes);
+        unsafe {
+            let grabbed = tablet.down && tablet.focus.is_some();
+            let same_surface = match (&tablet.focus, &target) {
+                (&Some(ref focus), &Some((ref surface, _, _))) => focus.surface == *surface,
+                _ => false
+            };
+            let entered = if grabbed || same_surface {
+                false
+            } else {
+                tablet.leave(display, time_msec);
+                match target {
+                    Some((surface, sx, sy)) => {
+                        if !tablet.enter(display, surface, (lx - sx, ly - sy)) {
+                            return false
+                        }
+                        true
+                    }
+                    None => return false
+                }
+            };
+            let (tool, (origin_x, origin_y)) = match tablet.focus {
+                Some(ref focus) => (focus.tool, focus.origin),
+                None => return false
+            };
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          tool,
+                          MOTION_EVENT,
+                          to_fixed(lx - origin_x),
+                          to_fixed(ly - origin_y));
+            // A surface that was just entered gets the whole state.
+            let axes = if entered { &tablet.axes } else { axes };
+            send_axes(tool, axes, &tablet.axes);
+            send_frame(tool, time_msec);
+        }
+        true
+    })
+}
+
+/// Takes the pen of a tablet out of proximity of the surface it was on.
+pub fn tool_proximity_out(device: &TabletToolHandle, time_msec: u32) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let display = state.display;
+        if let Some(tablet) = state.tablets.iter_mut().find(|tablet| tablet.device == *device) {
+            unsafe { tablet.leave(display, time_msec) };
+        }
+    });
+}
+
+/// Sends that the pen of a tablet touches it, or stopped touching it.
+///
+/// Returns false if the pen isn't on a surface of a client that uses
+/// tablets.
+pub fn tool_tip(device: &TabletToolHandle, down: bool, time_msec: u32) -> bool {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let display = state.display;
+        let tablet = match state.tablets.iter_mut().find(|tablet| tablet.device == *device) {
+            Some(tablet) => tablet,
+            None => return false
+        };
+        let tool = match tablet.focus {
+            Some(ref focus) => focus.tool,
+            None => return false
+        };
+        if tablet.down == down {
+            return true
+        }
+        tablet.down = down;
+        unsafe {
+            if down {
+                let serial = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_next_serial, display);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              tool,
+                              DOWN_EVENT,
+                              serial);
+            } else {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, tool, UP_EVENT);
+            }
+            send_frame(tool, time_msec);
+        }
+        true
+    })
+}
+
+/// Sends the press or release of a button of the pen of a tablet.
+///
+/// Returns false if the pen isn't on a surface of a client that uses
+/// tablets.
+pub fn tool_button(device: &TabletToolHandle, button: u32, pressed: bool, time_msec: u32) -> bool {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let display = state.display;
+        let tablet = match state.tablets.iter_mut().find(|tablet| tablet.device == *device) {
+            Some(tablet) => tablet,
+            None => return false
+        };
+        let tool = match tablet.focus {
+            Some(ref focus) => focus.tool,
+            None => return false
+        };
+        if pressed {
+            tablet.buttons.push(button);
+        } else {
+            tablet.buttons.retain(|&held| held != button);
+        }
+        let button_state = if pressed { BUTTON_PRESSED } else { BUTTON_RELEASED };
+        unsafe {
+            let serial = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_next_serial, display);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          tool,
+                          TOOL_BUTTON_EVENT,
+                          serial,
+                          button,
+                          button_state);
+            send_frame(tool, time_msec);
+        }
+        true
+    })
+}
+
+/// Sends the press or release of a button of a pad to a surface, which is
+/// the one with the keyboard focus.
+///
+/// The pad enters the surface first if it wasn't on it already. This needs
+/// the client to know a tablet, so a pad without a tablet sends nothing.
+pub fn pad_button(device: &TabletPadHandle,
+                  surface: Option<SurfaceHandle>,
+                  button: u32,
+                  pressed: bool,
+                  time_msec: u32) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let Tablets { display,
+                      ref tablets,
+                      ref mut pads,
+                      .. } = *state;
+        let pad = match pads.iter_mut().find(|pad| pad.device == *device) {
+            Some(pad) => pad,
+            None => return
+        };
+        unsafe {
+            let same_surface = match (&pad.focus, &surface) {
+                (&Some(ref focus), &Some(ref surface)) => focus.surface == *surface,
+                (&None, &None) => true,
+                _ => false
+            };
+            if !same_surface {
+                if let Some(focus) = pad.focus.take() {
+                    // The client destroyed the surface if it's gone, so it
+                    // knows the pad left it.
+                    if let Some(surface_resource) = surface_resource(&focus.surface) {
+                        let serial =
+                            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_next_serial, display);
+                        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                      wl_resource_post_event,
+                                      focus.pad,
+                                      LEAVE_EVENT,
+                                      serial,
+                                      surface_resource);
+                    }
+                }
+                if let Some(surface) = surface {
+                    pad.focus = pad_enter(display, &pad.resources, tablets, surface);
+                }
+            }
+            if let Some(ref focus) = pad.focus {
+                let button_state = if pressed { BUTTON_PRESSED } else { BUTTON_RELEASED };
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              focus.pad,
+                              PAD_BUTTON_EVENT,
+                              time_msec,
+                              button,
+                              button_state);
+            }
+        }
+    });
+}
+
+/// Brings a pad onto a surface, with the first tablet its client knows.
+unsafe fn pad_enter(display: *mut wl_display,
+                    pad_resources: &[*mut wl_resource],
+                    tablets: &[Tablet],
+                    surface: SurfaceHandle)
+                    -> Option<PadFocus> {
+    let surface_resource = surface_resource(&surface)?;
+    let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, surface_resource);
+    let pad = *pad_resources.iter().find(|&&pad| {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, pad) == client
+    })?;
+    let tablet = tablets.iter()
+                        .filter_map(|tablet| tablet.resources_of(client))
+                        .map(|resources| resources.tablet)
+                        .next()?;
+    let serial = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_next_serial, display);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  pad,
+                  ENTER_EVENT,
+                  serial,
+                  tablet,
+                  surface_resource);
+    Some(PadFocus { surface, pad })
+}
+
+/// Sends a tablet and its pen to a tablet seat.
+unsafe fn announce_tablet(seat: *mut wl_resource, tablet: &mut Tablet) {
+    let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, seat);
+    let tablet_resource = create_resource(client,
+                                          seat,
+                                          &zwp_tablet_v2_interface,
+                                          0,
+                                          &DESTROY_IMPLEMENTATION as *const _ as *const c_void,
+                                          Some(tablet_destroyed));
+    let tablet_resource = match tablet_resource {
+        Some(tablet_resource) => tablet_resource,
+        None => return
+    };
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  seat,
+                  TABLET_ADDED_EVENT,
+                  tablet_resource);
+    send_string(tablet_resource, NAME_EVENT, &tablet.name);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  tablet_resource,
+                  ID_EVENT,
+                  tablet.vendor,
+                  tablet.product);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  tablet_resource,
+                  TABLET_DONE_EVENT);
+    let tool = create_resource(client,
+                               seat,
+                               &zwp_tablet_tool_v2_interface,
+                               0,
+                               &TOOL_IMPLEMENTATION as *const _ as *const c_void,
+                               Some(tool_destroyed)).unwrap_or(ptr::null_mut());
+    if !tool.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      seat,
+                      TOOL_ADDED_EVENT,
+                      tool);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      tool,
+                      TYPE_EVENT,
+                      TYPE_PEN);
+        for &capability in &[CAPABILITY_TILT, CAPABILITY_PRESSURE, CAPABILITY_DISTANCE] {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          tool,
+                          CAPABILITY_EVENT,
+                          capability);
+        }
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      tool,
+                      TOOL_DONE_EVENT);
+    }
+    tablet.resources.push(SeatResources { tablet: tablet_resource,
+                                          tool });
+}
+
+/// Sends a pad and its group to a tablet seat.
+unsafe fn announce_pad(seat: *mut wl_resource, pad: &mut Pad) {
+    let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, seat);
+    let resource = create_resource(client,
+                                   seat,
+                                   &zwp_tablet_pad_v2_interface,
+                                   0,
+                                   &PAD_IMPLEMENTATION as *const _ as *const c_void,
+                                   Some(pad_destroyed));
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return
+    };
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  seat,
+                  PAD_ADDED_EVENT,
+                  resource);
+    // Nothing refers to the group later on, so it isn't kept.
+    let group = create_resource(client,
+                                seat,
+                                &zwp_tablet_pad_group_v2_interface,
+                                0,
+                                &DESTROY_IMPLEMENTATION as *const _ as *const c_void,
+                                None);
+    let group = match group {
+        Some(group) => group,
+        None => return
+    };
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  GROUP_EVENT,
+                  group);
+    let mut buttons: Vec<u32> = (0..pad.buttons).collect();
+    let mut buttons_array = wl_array { size: buttons.len() * mem::size_of::<u32>(),
+                                       alloc: buttons.len() * mem::size_of::<u32>(),
+                                       data: buttons.as_mut_ptr() as *mut c_void };
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  group,
+                  GROUP_BUTTONS_EVENT,
+                  &mut buttons_array as *mut wl_array);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  group,
+                  GROUP_DONE_EVENT);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  PAD_BUTTONS_EVENT,
+                  pad.buttons);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  PAD_DONE_EVENT);
+    pad.resources.push(resource);
+}
+
+/// Sends the axes that are set in `changed`. Tilt is sent on both axes, so
+/// the other one is taken from `current`.
+unsafe fn send_axes(tool: *mut wl_resource, changed: &ToolAxes, current: &ToolAxes) {
+    if let Some(pressure) = changed.pressure {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      tool,
+                      PRESSURE_EVENT,
+                      to_normalized(pressure));
+    }
+    if let Some(distance) = changed.distance {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      tool,
+                      DISTANCE_EVENT,
+                      to_normalized(distance));
+    }
+    if changed.tilt_x.is_some() || changed.tilt_y.is_some() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      tool,
+                      TILT_EVENT,
+                      to_fixed(current.tilt_x.unwrap_or(0.0)),
+                      to_fixed(current.tilt_y.unwrap_or(0.0)));
+    }
+}
+
+unsafe fn send_frame(tool: *mut wl_resource, time_msec: u32) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  tool,
+                  FRAME_EVENT,
+                  time_msec);
+}
+
+/// Scales a value between 0 and 1 to the range of the protocol.
+fn to_normalized(value: f64) -> u32 {
+    (value.max(0.0).min(1.0) * 65535.0).round() as u32
+}
+
+fn current_time_msec() -> u32 {
+    let time = current_time();
+    (time.as_secs() * 1000 + time.subsec_nanos() as u64 / 1_000_000) as u32
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, _: *mut c_void, version: u32, id: u32) {
+    bind_resource(client,
+                  &zwp_tablet_manager_v2_interface,
+                  version,
+                  id,
+                  &MANAGER_IMPLEMENTATION as *const _ as *const c_void,
+                  None);
+}
+
+unsafe extern "C" fn get_tablet_seat(client: *mut wl_client,
+                                     manager: *mut wl_resource,
+                                     id: u32,
+                                     _: *mut wl_resource) {
+    // There is only one seat, so every tablet belongs to it.
+    let seat = create_resource(client,
+                               manager,
+                               &zwp_tablet_seat_v2_interface,
+                               id,
+                               &DESTROY_IMPLEMENTATION as *const _ as *const c_void,
+                               Some(seat_destroyed));
+    let seat = match seat {
+        Some(seat) => seat,
+        None => return
+    };
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   state.seats.push(seat);
+                   for tablet in &mut state.tablets {
+                       announce_tablet(seat, tablet);
+                   }
+                   for pad in &mut state.pads {
+                       announce_pad(seat, pad);
+                   }
+               });
+}
+
+unsafe extern "C" fn set_cursor(_: *mut wl_client,
+                                tool: *mut wl_resource,
+                                serial: u32,
+                                surface: *mut wl_resource,
+                                hotspot_x: i32,
+                                hotspot_y: i32) {
+    // Only the client the pen is over can set the cursor, with the serial
+    // of the latest `proximity_in`.
+    let allowed = STATE.with(|state| {
+        state.borrow().tablets.iter().any(|tablet| match tablet.focus {
+            Some(ref focus) => focus.tool == tool && focus.serial == serial,
+            None => false
+        })
+    });
+    let compositor = match wlroots::compositor_handle() {
+        Some(compositor) if allowed => compositor,
+        _ => return
+    };
+    with_handles!([(compositor: {compositor})] => {
+        let server: &mut ::Server = compositor.into();
+        let ::Server { ref cursor,
+                       ref mut seat,
+                       .. } = *server;
+        with_handles!([(cursor: {cursor})] => {
+            let surface = if surface.is_null() {
+                ptr::null_mut()
+            } else {
+                wlr_surface_from_resource(surface as *mut _)
+            };
+            wlr_cursor_set_surface(cursor.as_ptr(), surface, hotspot_x, hotspot_y);
+            seat.cursor_image = None;
+        }).unwrap_or(())
+    }).unwrap_or(())
+}
+
+unsafe extern "C" fn set_feedback(_: *mut wl_client,
+                                  _: *mut wl_resource,
+                                  _: u32,
+                                  _: *const c_char,
+                                  _: u32) {
+    // There is nowhere to show what the buttons do.
+}
+
+unsafe extern "C" fn seat_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| state.borrow_mut().seats.retain(|&seat| seat != resource));
+}
+
+unsafe extern "C" fn tablet_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| {
+        for tablet in &mut state.borrow_mut().tablets {
+            for resources in tablet.resources.iter_mut().filter(|r| r.tablet == resource) {
+                resources.tablet = ptr::null_mut();
+            }
+        }
+    });
+}
+
+unsafe extern "C" fn tool_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| {
+        for tablet in &mut state.borrow_mut().tablets {
+            for resources in tablet.resources.iter_mut().filter(|r| r.tool == resource) {
+                resources.tool = ptr::null_mut();
+            }
+            let focused = tablet.focus.as_ref().map_or(false, |focus| focus.tool == resource);
+            if focused {
+                tablet.focus = None;
+                tablet.down = false;
+                tablet.buttons.clear();
+            }
+        }
+    });
+}
+
+unsafe extern "C" fn pad_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| {
+        for pad in &mut state.borrow_mut().pads {
+            pad.resources.retain(|&other| other != resource);
+            if pad.focus.as_ref().map_or(false, |focus| focus.pad == resource) {
+                pad.focus = None;
+            }
+        }
+    });
+}
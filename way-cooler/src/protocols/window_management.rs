@@ -0,0 +1,562 @@
+//! Implementation of the private `zway_cooler_window_management_v1` protocol.
+//!
+//! This is how the awesome client learns about the views of the compositor,
+//! and how it tells the compositor what to do with them.
+//!
+//! The state lives in a thread local instead of the `Server` because views
+//! change in places that only have access to the `Seat` or the `View`.
+
+use std::cell::RefCell;
+use std::os::raw::{c_int, c_void};
+use std::rc::{Rc, Weak};
+
+use wayland_sys::server::*;
+use wlroots::{self, Area, Compositor, Origin, Size};
+
+use super::interfaces::{zway_cooler_view_v1_interface,
+                        zway_cooler_window_management_v1_interface};
+use super::pointer_gestures::Gesture;
+use super::{bind_resource, create_global, create_resource, destroy_resource, to_fixed};
+
+/// The highest version of the protocol that we support.
+const VERSION: u32 = 4;
+
+/// Opcodes of the events of `zway_cooler_window_management_v1`.
+const VIEW_EVENT: u32 = 0;
+const AXIS_EVENT: u32 = 1;
+const AXIS_FRAME_EVENT: u32 = 2;
+const GESTURE_BEGIN_EVENT: u32 = 3;
+const GESTURE_UPDATE_EVENT: u32 = 4;
+const GESTURE_END_EVENT: u32 = 5;
+
+/// Opcodes of the events of `zway_cooler_view_v1`.
+const GEOMETRY_EVENT: u32 = 0;
+const FOCUSED_EVENT: u32 = 1;
+const DONE_EVENT: u32 = 2;
+const CLOSED_EVENT: u32 = 3;
+const MINIMIZED_EVENT: u32 = 4;
+
+/// The version of the protocol that added minimizing views.
+const MINIMIZED_SINCE: c_int = 2;
+/// The version of the protocol that added the axis events.
+const AXIS_SINCE: c_int = 3;
+/// The version of the protocol that added claiming gestures.
+const GESTURE_SINCE: c_int = 4;
+
+thread_local! {
+    static STATE: RefCell<WindowManagement> = RefCell::new(WindowManagement::default());
+}
+
+#[derive(Debug, Default)]
+struct WindowManagement {
+    /// All the bound `zway_cooler_window_management_v1` resources.
+    managers: Vec<*mut wl_resource>,
+    /// All the `zway_cooler_view_v1` resources of mapped views.
+    views: Vec<ViewResource>,
+    /// Whether an axis event was sent since the last axis frame.
+    axis_pending: bool,
+    /// The gestures the window managers bound.
+    claims: Vec<Claim>,
+    /// The gestures in progress and the managers that claimed them.
+    gestures: Vec<(Gesture, Vec<*mut wl_resource>)>
+}
+
+/// A kind of gesture with a number of fingers that a window manager claimed,
+/// so it isn't sent to the surface under the cursor.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Claim {
+    manager: *mut wl_resource,
+    gesture: u32,
+    fingers: u32
+}
+
+#[derive(Debug)]
+struct ViewResource {
+    resource: *mut wl_resource,
+    view: Weak<::View>,
+    /// The last geometry that was sent, so we don't spam the client on every
+    /// commit.
+    geometry: Area
+}
+
+#[repr(C)]
+struct ManagementImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    claim_gesture: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, u32),
+    release_gesture: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, u32)
+}
+
+#[repr(C)]
+struct ViewImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    set_position: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32, i32),
+    set_size: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32, i32),
+    focus: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    close: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    set_minimized: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32)
+}
+
+static MANAGEMENT_IMPLEMENTATION: ManagementImplementation =
+    ManagementImplementation { destroy: destroy_resource,
+                               claim_gesture,
+                               release_gesture };
+
+static VIEW_IMPLEMENTATION: ViewImplementation = ViewImplementation { destroy: destroy_resource,
+                                                                      set_position,
+                                                                      set_size,
+                                                                      focus,
+                                                                      close,
+                                                                      set_minimized };
+
+impl ViewResource {
+    fn is(&self, view: &::View) -> bool {
+        self.view.upgrade().map(|v| *v == *view).unwrap_or(false)
+    }
+}
+
+impl WindowManagement {
+    /// Creates a new view resource for the client of the manager and sends
+    /// the initial state of the view.
+    unsafe fn announce(&mut self, manager: *mut wl_resource, view: &Rc<::View>, focused: bool) {
+        let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, manager);
+        let resource = create_resource(client,
+                                       manager,
+                                       &zway_cooler_view_v1_interface,
+                                       0,
+                                       &VIEW_IMPLEMENTATION as *const _ as *const c_void,
+                                       Some(view_destroyed));
+        let resource = match resource {
+            Some(resource) => resource,
+            None => return
+        };
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      manager,
+                      VIEW_EVENT,
+                      resource);
+        let geometry = view_geometry(view);
+        send_geometry(resource, geometry);
+        send_focused(resource, focused);
+        send_minimized(resource, view.minimized.get());
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, DONE_EVENT);
+        self.views.push(ViewResource { resource,
+                                       view: Rc::downgrade(view),
+                                       geometry });
+    }
+}
+
+/// Advertises the window management global on the compositor's display.
+pub fn init(compositor: &mut Compositor) {
+    create_global(compositor.display as *mut wl_display,
+                  unsafe { &zway_cooler_window_management_v1_interface },
+                  VERSION,
+                  bind,
+                  "window management");
+}
+
+/// Announces a newly mapped view to every window manager.
+pub fn view_mapped(view: &Rc<::View>) {
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   for manager in state.managers.clone() {
+                       unsafe { state.announce(manager, view, false) }
+                   }
+               });
+}
+
+/// Tells every window manager that the view is gone.
+pub fn view_unmapped(view: &::View) {
+    STATE.with(|state| {
+        state.borrow_mut().views.retain(|view_resource| {
+            if !view_resource.is(view) {
+                return true
+            }
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              view_resource.resource,
+                              CLOSED_EVENT);
+            }
+            false
+        })
+    });
+}
+
+/// Sends the new geometry of the view, if it actually changed.
+pub fn geometry_changed(view: &::View) {
+    let geometry = view_geometry(view);
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        for view_resource in state.views.iter_mut().filter(|v| v.is(view)) {
+            if view_resource.geometry == geometry {
+                continue
+            }
+            view_resource.geometry = geometry;
+            unsafe {
+                send_geometry(view_resource.resource, geometry);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              view_resource.resource,
+                              DONE_EVENT);
+            }
+        }
+    });
+}
+
+/// Sends the new keyboard focus state of the view.
+pub fn focus_changed(view: &::View, focused: bool) {
+    STATE.with(|state| {
+        for view_resource in state.borrow().views.iter().filter(|v| v.is(view)) {
+            unsafe {
+                send_focused(view_resource.resource, focused);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              view_resource.resource,
+                              DONE_EVENT);
+            }
+        }
+    });
+}
+
+/// Sends whether the view is minimized now.
+pub fn minimized_changed(view: &::View, minimized: bool) {
+    STATE.with(|state| {
+        for view_resource in state.borrow().views.iter().filter(|v| v.is(view)) {
+            unsafe {
+                if !send_minimized(view_resource.resource, minimized) {
+                    continue
+                }
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              view_resource.resource,
+                              DONE_EVENT);
+            }
+        }
+    });
+}
+
+/// Sends a scroll of the pointer to every window manager, wherever the
+/// pointer is.
+pub fn pointer_axis(time_msec: u32, source: u32, orientation: u32, value: f64, discrete: i32) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        for &manager in &state.managers {
+            unsafe {
+                if !has_version(manager, AXIS_SINCE) {
+                    continue
+                }
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              manager,
+                              AXIS_EVENT,
+                              time_msec,
+                              source,
+                              orientation,
+                              to_fixed(value),
+                              discrete);
+            }
+        }
+        state.axis_pending = true;
+    });
+}
+
+/// Ends the group of axis events sent since the last frame of the pointer.
+///
+/// Frames that only had motion or buttons aren't sent.
+pub fn pointer_frame() {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if !state.axis_pending {
+            return
+        }
+        state.axis_pending = false;
+        for &manager in &state.managers {
+            unsafe {
+                if !has_version(manager, AXIS_SINCE) {
+                    continue
+                }
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              manager,
+                              AXIS_FRAME_EVENT);
+            }
+        }
+    });
+}
+
+/// Begins a gesture of a touchpad for the window managers that claimed it.
+///
+/// Returns whether one did, in which case the gesture is not for the surface
+/// under the cursor.
+pub fn gesture_begin(gesture: Gesture, fingers: u32, time_msec: u32) -> bool {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let managers: Vec<*mut wl_resource> =
+            state.claims
+                 .iter()
+                 .filter(|claim| claim.gesture == gesture as u32 && claim.fingers == fingers)
+                 .map(|claim| claim.manager)
+                 .filter(|&manager| unsafe { has_version(manager, GESTURE_SINCE) })
+                 .collect();
+        for &manager in &managers {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              manager,
+                              GESTURE_BEGIN_EVENT,
+                              time_msec,
+                              gesture as u32,
+                              fingers);
+            }
+        }
+        state.gestures.retain(|&(other, _)| other != gesture);
+        if managers.is_empty() {
+            return false
+        }
+        state.gestures.push((gesture, managers));
+        true
+    })
+}
+
+/// Sends how the gesture in progress moved to the window managers that
+/// claimed it.
+pub fn gesture_update(gesture: Gesture,
+                      (dx, dy): (f64, f64),
+                      scale: f64,
+                      rotation: f64,
+                      time_msec: u32) {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let managers = state.gestures
+                            .iter()
+                            .filter(|&&(other, _)| other == gesture)
+                            .flat_map(|&(_, ref managers)| managers);
+        for &manager in managers {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              manager,
+                              GESTURE_UPDATE_EVENT,
+                              time_msec,
+                              gesture as u32,
+                              to_fixed(dx),
+                              to_fixed(dy),
+                              to_fixed(scale),
+                              to_fixed(rotation));
+            }
+        }
+    });
+}
+
+/// Tells the window managers that claimed the gesture in progress that it
+/// ended.
+pub fn gesture_end(gesture: Gesture, cancelled: bool, time_msec: u32) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let position = state.gestures.iter().position(|&(other, _)| other == gesture);
+        let (_, managers) = match position {
+            Some(position) => state.gestures.remove(position),
+            None => return
+        };
+        for manager in managers {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              manager,
+                              GESTURE_END_EVENT,
+                              time_msec,
+                              gesture as u32,
+                              cancelled as u32);
+            }
+        }
+    });
+}
+
+fn view_geometry(view: &::View) -> Area {
+    Area::new(view.origin.get(), view.get_size())
+}
+
+/// Whether the client bound a version of the protocol that has the event.
+unsafe fn has_version(resource: *mut wl_resource, since: c_int) -> bool {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, resource) >= since
+}
+
+unsafe fn send_geometry(resource: *mut wl_resource, geometry: Area) {
+    let Area { origin: Origin { x, y },
+               size: Size { width, height } } = geometry;
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  GEOMETRY_EVENT,
+                  x,
+                  y,
+                  width,
+                  height);
+}
+
+unsafe fn send_focused(resource: *mut wl_resource, focused: bool) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  FOCUSED_EVENT,
+                  focused as u32);
+}
+
+/// Sends the minimized state, unless the client bound a version without it.
+///
+/// Returns whether the event was sent.
+unsafe fn send_minimized(resource: *mut wl_resource, minimized: bool) -> bool {
+    if !has_version(resource, MINIMIZED_SINCE) {
+        return false
+    }
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  MINIMIZED_EVENT,
+                  minimized as u32);
+    true
+}
+
+/// Gets the view of a `zway_cooler_view_v1` resource, if it is still mapped.
+fn resource_view(resource: *mut wl_resource) -> Option<Rc<::View>> {
+    STATE.with(|state| {
+                   state.borrow()
+                        .views
+                        .iter()
+                        .find(|view_resource| view_resource.resource == resource)
+                        .and_then(|view_resource| view_resource.view.upgrade())
+               })
+}
+
+/// Gets all the mapped views and the focused view from the compositor.
+fn mapped_views() -> (Vec<Rc<::View>>, Option<Rc<::View>>) {
+    let compositor = match wlroots::compositor_handle() {
+        Some(compositor) => compositor,
+        None => return (Vec::new(), None)
+    };
+    with_handles!([(compositor: {compositor})] => {
+        let server: &mut ::Server = compositor.into();
+        (server.views.clone(), server.seat.focused.clone())
+    }).unwrap_or_else(|_| (Vec::new(), None))
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, _: *mut c_void, version: u32, id: u32) {
+    let resource = bind_resource(client,
+                                 &zway_cooler_window_management_v1_interface,
+                                 version,
+                                 id,
+                                 &MANAGEMENT_IMPLEMENTATION as *const _ as *const c_void,
+                                 Some(management_destroyed));
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return
+    };
+    let (views, focused) = mapped_views();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.managers.push(resource);
+        for view in &views {
+            let is_focused = focused.as_ref() == Some(view);
+            state.announce(resource, view, is_focused);
+        }
+    });
+}
+
+unsafe extern "C" fn management_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.managers.retain(|&manager| manager != resource);
+        state.claims.retain(|claim| claim.manager != resource);
+        for &mut (_, ref mut managers) in &mut state.gestures {
+            managers.retain(|&manager| manager != resource);
+        }
+    });
+}
+
+unsafe extern "C" fn claim_gesture(_: *mut wl_client,
+                                   resource: *mut wl_resource,
+                                   gesture: u32,
+                                   fingers: u32) {
+    let claim = Claim { manager: resource,
+                        gesture,
+                        fingers };
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if !state.claims.contains(&claim) {
+            state.claims.push(claim);
+        }
+    });
+}
+
+unsafe extern "C" fn release_gesture(_: *mut wl_client,
+                                     resource: *mut wl_resource,
+                                     gesture: u32,
+                                     fingers: u32) {
+    let claim = Claim { manager: resource,
+                        gesture,
+                        fingers };
+    STATE.with(|state| state.borrow_mut().claims.retain(|other| *other != claim));
+}
+
+unsafe extern "C" fn view_destroyed(resource: *mut wl_resource) {
+    STATE.with(|state| {
+                   state.borrow_mut()
+                        .views
+                        .retain(|view_resource| view_resource.resource != resource)
+               });
+}
+
+unsafe extern "C" fn set_position(_: *mut wl_client, resource: *mut wl_resource, x: i32, y: i32) {
+    if let Some(view) = resource_view(resource) {
+        view.move_to(Origin::new(x, y));
+        geometry_changed(&view);
+    }
+}
+
+unsafe extern "C" fn set_size(_: *mut wl_client,
+                              resource: *mut wl_resource,
+                              width: i32,
+                              height: i32) {
+    if let Some(view) = resource_view(resource) {
+        view.move_resize(Area::new(view.origin.get(), Size::new(width, height)));
+    }
+}
+
+unsafe extern "C" fn focus(_: *mut wl_client, resource: *mut wl_resource) {
+    let view = match resource_view(resource) {
+        Some(view) => view,
+        None => return
+    };
+    if let Some(compositor) = wlroots::compositor_handle() {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            if view.minimized.get() {
+                server.set_minimized(&view, false);
+            } else {
+                let ::Server { ref mut seat,
+                               ref mut views,
+                               .. } = *server;
+                seat.focus_view(view, views);
+            }
+        }).unwrap_or(());
+    }
+}
+
+unsafe extern "C" fn close(_: *mut wl_client, resource: *mut wl_resource) {
+    if let Some(view) = resource_view(resource) {
+        view.close();
+    }
+}
+
+unsafe extern "C" fn set_minimized(_: *mut wl_client, resource: *mut wl_resource, minimized: u32) {
+    let view = match resource_view(resource) {
+        Some(view) => view,
+        None => return
+    };
+    if let Some(compositor) = wlroots::compositor_handle() {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            server.set_minimized(&view, minimized != 0);
+        }).unwrap_or(());
+    }
+}
@@ -0,0 +1,270 @@
+//! Implementation of `zxdg_decoration_manager_v1`, which lets xdg-shell
+//! clients negotiate who draws their decorations.
+//!
+//! Clients that ask for a mode get it. Clients that don't get the mode from
+//! the config. Server side decorated views get a border drawn around them
+//! by the compositor.
+//!
+//! The state lives in a thread local because it is needed while rendering
+//! and damaging views, which only have the `View`.
+
+use std::cell::RefCell;
+use std::os::raw::c_void;
+use std::ptr;
+
+use wayland_sys::server::*;
+use wlroots::{self, Area, Compositor};
+
+use super::interfaces::{zxdg_decoration_manager_v1_interface,
+                        zxdg_toplevel_decoration_v1_interface};
+use super::{bind_resource, create_global, create_resource, destroy_resource, post_error};
+use output::damage;
+use DecorationMode;
+
+/// The highest version of the protocol that we support.
+const VERSION: u32 = 1;
+
+/// Opcode of the `configure` event of `zxdg_toplevel_decoration_v1`.
+const CONFIGURE_EVENT: u32 = 0;
+
+/// Values of the `mode` enum of `zxdg_toplevel_decoration_v1`.
+const MODE_CLIENT_SIDE: u32 = 1;
+const MODE_SERVER_SIDE: u32 = 2;
+
+/// The `already_constructed` error of `zxdg_toplevel_decoration_v1`.
+const ALREADY_CONSTRUCTED_ERROR: u32 = 1;
+
+thread_local! {
+    static STATE: RefCell<Decorations> = RefCell::new(Decorations::default());
+}
+
+#[derive(Debug)]
+struct Decorations {
+    decorations: Vec<ToplevelDecoration>,
+    /// The mode of clients that don't ask for one.
+    default_mode: DecorationMode,
+    border_width: i32
+}
+
+#[derive(Debug)]
+struct ToplevelDecoration {
+    resource: *mut wl_resource,
+    /// The `wlr_xdg_surface` of the toplevel, which is the user data of its
+    /// `xdg_toplevel` resource.
+    xdg_surface: *mut c_void,
+    /// The mode the client asked for, if any.
+    requested: Option<DecorationMode>
+}
+
+#[repr(C)]
+struct ManagerImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    get_toplevel_decoration:
+        unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, *mut wl_resource)
+}
+
+#[repr(C)]
+struct DecorationImplementation {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    set_mode: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32),
+    unset_mode: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+static MANAGER_IMPLEMENTATION: ManagerImplementation =
+    ManagerImplementation { destroy: destroy_resource,
+                            get_toplevel_decoration };
+
+static DECORATION_IMPLEMENTATION: DecorationImplementation =
+    DecorationImplementation { destroy: destroy_resource,
+                               set_mode,
+                               unset_mode };
+
+impl Default for Decorations {
+    fn default() -> Self {
+        Decorations { decorations: Vec::new(),
+                      default_mode: DecorationMode::ServerSide,
+                      border_width: 0 }
+    }
+}
+
+impl Decorations {
+    fn mode_of(&self, decoration: &ToplevelDecoration) -> DecorationMode {
+        decoration.requested.unwrap_or(self.default_mode)
+    }
+
+    fn find(&self, xdg_surface: *mut c_void) -> Option<&ToplevelDecoration> {
+        self.decorations
+            .iter()
+            .find(|decoration| decoration.xdg_surface == xdg_surface)
+    }
+}
+
+/// Advertises the decoration manager global on the compositor's display.
+pub fn init(compositor: &mut Compositor) {
+    create_global(compositor.display as *mut wl_display,
+                  unsafe { &zxdg_decoration_manager_v1_interface },
+                  VERSION,
+                  bind,
+                  "xdg decoration manager");
+}
+
+/// Applies the decoration settings of the config, reconfiguring the
+/// decorations of clients without a preference.
+pub fn apply_config(config: &::Config) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.default_mode = config.decoration_mode;
+        state.border_width = config.border_width;
+        for decoration in state.decorations.iter().filter(|d| d.requested.is_none()) {
+            unsafe { send_configure(decoration.resource, config.decoration_mode) }
+        }
+    });
+}
+
+/// How wide the border drawn around the view by the compositor is.
+///
+/// This is 0 if the client decorates itself.
+pub fn border_width(view: &::View) -> i32 {
+    let xdg_surface = match view.shell {
+        ::Shell::Xdg(ref xdg_surface) => {
+            with_handles!([(xdg_surface: {xdg_surface})] => {
+                xdg_surface.as_ptr() as *mut c_void
+            }).unwrap_or(ptr::null_mut())
+        }
+        // Only stable xdg-shell toplevels can negotiate decorations.
+        _ => return 0
+    };
+    STATE.with(|state| {
+        let state = state.borrow();
+        match state.find(xdg_surface) {
+            Some(decoration) if state.mode_of(decoration) == DecorationMode::ServerSide => {
+                state.border_width
+            }
+            _ => 0
+        }
+    })
+}
+
+unsafe fn send_configure(resource: *mut wl_resource, mode: DecorationMode) {
+    let mode = match mode {
+        DecorationMode::ClientSide => MODE_CLIENT_SIDE,
+        DecorationMode::ServerSide => MODE_SERVER_SIDE
+    };
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  CONFIGURE_EVENT,
+                  mode);
+}
+
+/// Updates the mode the client asked for and tells it which mode to use.
+unsafe fn request_mode(resource: *mut wl_resource, requested: Option<DecorationMode>) {
+    let xdg_surface = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let default_mode = state.default_mode;
+        let decoration = state.decorations
+                              .iter_mut()
+                              .find(|decoration| decoration.resource == resource)?;
+        decoration.requested = requested;
+        send_configure(resource, requested.unwrap_or(default_mode));
+        Some(decoration.xdg_surface)
+    });
+    if let Some(xdg_surface) = xdg_surface {
+        update_decorated_view(xdg_surface);
+    }
+}
+
+/// Damages the view of the toplevel, if it is mapped, as its border changed.
+///
+/// The decoration configure only applies with the next configure of the
+/// toplevel, so one is sent for its current size.
+fn update_decorated_view(xdg_surface: *mut c_void) {
+    let compositor = match wlroots::compositor_handle() {
+        Some(compositor) => compositor,
+        None => return
+    };
+    with_handles!([(compositor: {compositor})] => {
+        let server: &mut ::Server = compositor.into();
+        for view in &server.views {
+            if let ::Shell::Xdg(ref shell) = view.shell {
+                let is_decorated = with_handles!([(shell: {shell})] => {
+                    shell.as_ptr() as *mut c_void == xdg_surface
+                }).unwrap_or(false);
+                if is_decorated {
+                    damage::damage_view(view);
+                    view.move_resize(Area::new(view.origin.get(), view.get_size()));
+                }
+            }
+        }
+    }).unwrap_or(())
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, _: *mut c_void, version: u32, id: u32) {
+    bind_resource(client,
+                  &zxdg_decoration_manager_v1_interface,
+                  version,
+                  id,
+                  &MANAGER_IMPLEMENTATION as *const _ as *const c_void,
+                  None);
+}
+
+unsafe extern "C" fn get_toplevel_decoration(client: *mut wl_client,
+                                             manager: *mut wl_resource,
+                                             id: u32,
+                                             toplevel: *mut wl_resource) {
+    let xdg_surface = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, toplevel);
+    let exists = STATE.with(|state| state.borrow().find(xdg_surface).is_some());
+    if exists {
+        post_error(manager,
+                   ALREADY_CONSTRUCTED_ERROR,
+                   "xdg_toplevel already has a decoration object");
+        return
+    }
+    let resource = create_resource(client,
+                                   manager,
+                                   &zxdg_toplevel_decoration_v1_interface,
+                                   id,
+                                   &DECORATION_IMPLEMENTATION as *const _ as *const c_void,
+                                   Some(decoration_destroyed));
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return
+    };
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.decorations.push(ToplevelDecoration { resource,
+                                                    xdg_surface,
+                                                    requested: None });
+        send_configure(resource, state.default_mode);
+    });
+}
+
+unsafe extern "C" fn set_mode(_: *mut wl_client, resource: *mut wl_resource, mode: u32) {
+    let mode = match mode {
+        MODE_CLIENT_SIDE => DecorationMode::ClientSide,
+        MODE_SERVER_SIDE => DecorationMode::ServerSide,
+        _ => {
+            warn!("Client requested unknown decoration mode {}", mode);
+            return
+        }
+    };
+    request_mode(resource, Some(mode));
+}
+
+unsafe extern "C" fn unset_mode(_: *mut wl_client, resource: *mut wl_resource) {
+    request_mode(resource, None);
+}
+
+unsafe extern "C" fn decoration_destroyed(resource: *mut wl_resource) {
+    let xdg_surface = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let index = state.decorations
+                         .iter()
+                         .position(|decoration| decoration.resource == resource)?;
+        Some(state.decorations.remove(index).xdg_surface)
+    });
+    // The view goes back to decorating itself, so the border is gone.
+    if let Some(xdg_surface) = xdg_surface {
+        update_decorated_view(xdg_surface);
+    }
+}
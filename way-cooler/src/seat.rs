@@ -1,12 +1,34 @@
-use std::collections::HashSet;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ptr;
 use std::rc::Rc;
 use std::time::Duration;
+use output::damage;
+use protocols::{pointer_constraints, window_management};
+use wayland_sys::common::wl_list;
+use wayland_sys::server::*;
 use wlroots;
 use wlroots::events::seat_events::SetCursorEvent;
-use wlroots::pointer_events::ButtonEvent;
+use wlroots::pointer_events::{AxisEvent, ButtonEvent};
 use wlroots::utils::{current_time, Edges};
-use wlroots::{Area, CompositorHandle, Cursor, CursorHandle, DragIconHandle, Origin, SeatHandle,
-              SeatHandler, Size, SurfaceHandle, SurfaceHandler, XCursorManager};
+use wlroots::wlroots_sys::{wlr_seat, wlr_seat_client_for_wl_client, wlr_seat_touch_end_grab,
+                           wlr_seat_touch_grab, wlr_seat_touch_has_grab, wlr_seat_touch_notify_up,
+                           wlr_seat_touch_start_grab, wlr_touch_grab_interface, wlr_touch_point};
+use wlroots::{Area, CompositorHandle, Cursor, CursorHandle, DragIconHandle, KeyboardModifier,
+              Layer, Origin, SeatHandle, SeatHandler, Size, SurfaceHandle, SurfaceHandler,
+              XCursorManager};
+
+/// Opcode of `wl_touch.cancel`.
+const TOUCH_CANCEL_EVENT: u32 = 4;
+
+/// A touch grab that sends nothing to the clients, used to make wlroots
+/// forget cancelled points without sending `up` for them.
+static CANCELLED_GRAB_INTERFACE: wlr_touch_grab_interface =
+    wlr_touch_grab_interface { down: Some(cancelled_grab_down),
+                               up: Some(cancelled_grab_point),
+                               motion: Some(cancelled_grab_point),
+                               enter: Some(cancelled_grab_point),
+                               cancel: Some(cancelled_grab_cancel) };
 
 #[derive(Debug, Default)]
 pub struct SeatManager;
@@ -25,50 +47,208 @@ pub enum Action {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct DragIcon {
-    pub handle: DragIconHandle
+    pub handle: DragIconHandle,
+    pub mapped: Cell<bool>,
+    /// Where the icon is drawn, in layout coordinates.
+    ///
+    /// This is empty while it isn't drawn.
+    pub extents: Cell<Area>
 }
 
-#[derive(Debug, Default, Clone, Eq, PartialEq)]
+impl DragIcon {
+    pub fn new(handle: DragIconHandle) -> Self {
+        DragIcon { handle,
+                   mapped: Cell::new(false),
+                   extents: Cell::new(Area::default()) }
+    }
+
+    pub fn surface(&self) -> SurfaceHandle {
+        with_handles!([(drag_icon: {&self.handle})] => {
+            drag_icon.surface()
+        }).unwrap()
+    }
+
+    /// Places the icon under the cursor, or under the touch point that
+    /// started the drag, damaging where it was and where it is now.
+    pub fn update_position(&self, cursor: &Cursor, touch_points: &HashMap<i32, TouchPoint>) {
+        let extents = with_handles!([(drag_icon: {&self.handle}),
+                                     (surface: {self.surface()})] => {
+            let position = if !self.mapped.get() {
+                None
+            } else if drag_icon.is_pointer() {
+                Some(cursor.coords())
+            } else {
+                touch_points.get(&drag_icon.touch_id()).map(|point| point.position)
+            };
+            match position {
+                Some((x, y)) => {
+                    let (sx, sy) = drag_icon.position();
+                    let (width, height) = surface.current_state().size();
+                    Area::new(Origin::new(x as i32 + sx, y as i32 + sy),
+                              Size::new(width, height))
+                }
+                None => Area::default()
+            }
+        }).unwrap_or_default();
+        damage::damage_area(self.extents.get());
+        damage::damage_area(extents);
+        self.extents.set(extents);
+    }
+}
+
+/// A point of a touch device that is down on a surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchPoint {
+    /// Where the point is, in layout coordinates.
+    pub position: (f64, f64),
+    /// Where the surface it went down on was, in layout coordinates.
+    ///
+    /// The point stays on that surface until it goes up.
+    pub surface_origin: (f64, f64),
+    /// The client of that surface.
+    pub client: *mut wl_client
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Seat {
     pub seat: SeatHandle,
     pub focused: Option<Rc<::View>>,
+    /// A layer surface (e.g a launcher) that has taken the keyboard focus.
+    pub focused_layer: Option<Rc<::LayerSurface>>,
     pub action: Option<Action>,
-    pub has_client_cursor: bool,
-    pub meta: bool,
-    pub drag_icons: HashSet<DragIcon>
+    /// The cursor image from the theme that is shown, or `None` if a client
+    /// set its own.
+    pub cursor_image: Option<&'static str>,
+    /// The button that started a move or resize from the compositor.
+    ///
+    /// Its press was not sent to the client, so its release isn't either.
+    pub grab_button: Option<u32>,
+    pub drag_icons: Vec<DragIcon>,
+    /// The touch points that are down, by touch id.
+    pub touch_points: HashMap<i32, TouchPoint>
 }
 
 impl Seat {
     pub fn new(seat: SeatHandle) -> Seat {
+        // The server starts out showing the default image.
         Seat { seat,
-               meta: false,
+               cursor_image: Some("left_ptr"),
                ..Seat::default() }
     }
 
+    /// The modifiers held down on the keyboard of the seat.
+    pub fn keyboard_modifiers(&self) -> KeyboardModifier {
+        let keyboard = with_handles!([(seat: {&self.seat})] => {
+            seat.get_keyboard()
+        }).unwrap();
+        match keyboard {
+            Some(keyboard) => {
+                with_handles!([(keyboard: {keyboard})] => {
+                    keyboard.get_modifiers()
+                }).unwrap_or_else(|_| KeyboardModifier::empty())
+            }
+            None => KeyboardModifier::empty()
+        }
+    }
+
+    /// Shows an image of the cursor theme as the cursor.
+    pub fn set_cursor_image(&mut self,
+                            image: &'static str,
+                            cursor: &mut Cursor,
+                            xcursor_manager: &mut XCursorManager) {
+        if self.cursor_image != Some(image) {
+            xcursor_manager.set_cursor_image(image.to_string(), cursor);
+            self.cursor_image = Some(image);
+        }
+    }
+
+    /// Stops moving or resizing a view.
+    ///
+    /// The pointer enters the surface under it again, so its client sets
+    /// the cursor image back.
+    pub fn end_action(&mut self,
+                      cursor: &mut Cursor,
+                      xcursor_manager: &mut XCursorManager,
+                      views: &mut [Rc<::View>],
+                      layer_surfaces: &[Rc<::LayerSurface>]) {
+        if self.action.take().is_none() {
+            return
+        }
+        self.set_cursor_image("left_ptr", cursor, xcursor_manager);
+        dehandle!(
+            @seat = {&self.seat};
+            seat.pointer_clear_focus()
+        );
+        self.update_cursor_position(cursor, xcursor_manager, views, layer_surfaces, None);
+    }
+
+    /// Dismisses the popups of the focused client by ending the grab they
+    /// hold on the seat.
+    pub fn dismiss_popups(&mut self) {
+        dehandle!(
+            @seat = {&self.seat};
+            if seat.pointer_has_grab() {
+                seat.pointer_end_grab();
+            }
+            if seat.keyboard_has_grab() {
+                seat.keyboard_end_grab();
+            }
+        )
+    }
+
     pub fn clear_focus(&mut self) {
+        self.dismiss_popups();
         if let Some(focused_view) = self.focused.take() {
             focused_view.activate(false);
+            damage::damage_view(&focused_view);
+            window_management::focus_changed(&focused_view, false);
         }
+        pointer_constraints::focus_changed(None);
         dehandle!(@seat = {&self.seat};
                   seat.keyboard_clear_focus())
     }
 
+    /// Focuses the view on top that can be focused, if there is one.
+    pub fn focus_next_view(&mut self, views: &mut Vec<Rc<::View>>) {
+        let next = views.iter()
+                        .find(|view| !view.minimized.get() && view.accepts_focus())
+                        .cloned();
+        match next {
+            Some(next) => self.focus_view(next, views),
+            None => self.clear_focus()
+        }
+    }
+
     pub fn focus_view(&mut self, view: Rc<::View>, views: &mut Vec<Rc<::View>>) {
+        if !view.accepts_focus() {
+            return
+        }
+        if self.focused.as_ref() == Some(&view) {
+            return
+        }
+        // Otherwise the grab would keep the keyboard on the popup.
+        self.dismiss_popups();
         if let Some(ref focused) = self.focused {
-            if *focused == view {
-                return
-            }
             focused.activate(false);
+            damage::damage_view(focused);
+            window_management::focus_changed(focused, false);
         }
         self.focused = Some(view.clone());
+        self.focused_layer = None;
         view.activate(true);
+        window_management::focus_changed(&view, true);
+        pointer_constraints::focus_changed(Some(&view.surface()));
 
         if let Some(idx) = views.iter().position(|v| *v == view) {
             let v = views.remove(idx);
-            views.insert(0, v);
+            // Override redirect views stay above it, they are stacked first.
+            let top = views.iter().take_while(|v| v.is_override_redirect()).count();
+            views.insert(top, v);
         }
+        // It's now stacked on top, so all of it needs to be repainted.
+        damage::damage_view(&view);
 
         dehandle!(
             @seat = {&self.seat};
@@ -82,12 +262,53 @@ impl Seat {
         );
     }
 
+    /// Gives the keyboard focus to a layer surface, taking it away from the
+    /// focused view.
+    pub fn focus_layer_surface(&mut self, layer_surface: Rc<::LayerSurface>) {
+        self.clear_focus();
+        let surface = layer_surface.surface();
+        self.focused_layer = Some(layer_surface);
+        dehandle!(
+            @seat = {&self.seat};
+            if let Some(keyboard) = seat.get_keyboard() {
+                with_handles!([(keyboard: {keyboard}), (surface: {surface})] => {
+                    seat.keyboard_notify_enter(surface,
+                                               &mut keyboard.keycodes(),
+                                               &mut keyboard.get_modifier_masks());
+                }).unwrap();
+            }
+        );
+    }
+
     pub fn send_button(&self, event: &ButtonEvent) {
+        self.send_pointer_button(event.time_msec(), event.button(), event.state() as u32)
+    }
+
+    /// Sends a button to the surface under the pointer, for devices that act
+    /// like a pointer.
+    pub fn send_pointer_button(&self, time_msec: u32, button: u32, state: u32) {
+        dehandle!(
+            @seat = {&self.seat};
+            seat.pointer_notify_button(Duration::from_millis(time_msec as _), button, state));
+    }
+
+    /// Sends a scroll to the surface under the pointer.
+    pub fn send_axis(&self, event: &AxisEvent) {
         dehandle!(
             @seat = {&self.seat};
-            seat.pointer_notify_button(Duration::from_millis(event.time_msec() as _),
-            event.button(),
-            event.state() as u32));
+            seat.pointer_notify_axis(Duration::from_millis(event.time_msec() as _),
+                                     event.orientation(),
+                                     event.delta(),
+                                     event.delta_discrete(),
+                                     event.source()));
+    }
+
+    /// Tells the surface under the pointer that the events sent since the
+    /// last frame happened together.
+    pub fn send_pointer_frame(&self) {
+        dehandle!(
+            @seat = {&self.seat};
+            seat.pointer_notify_frame());
     }
 
     pub fn move_view<O>(&mut self, cursor: &mut Cursor, view: &::View, start: O)
@@ -104,7 +325,8 @@ impl Seat {
             }
             Some(start) => {
                 let pos = Origin::new(lx as i32 - start.x, ly as i32 - start.y);
-                view.origin.replace(pos);
+                view.move_to(pos);
+                window_management::geometry_changed(view);
             }
         };
     }
@@ -129,16 +351,36 @@ impl Seat {
         }).unwrap();
     }
 
-    pub fn view_at_pointer(views: &mut [Rc<::View>],
-                           cursor: &mut Cursor)
-                           -> (Option<Rc<::View>>, Option<SurfaceHandle>, f64, f64) {
-        for view in views {
+    /// The edges to resize when a view is grabbed at the cursor, based on
+    /// which quadrant of the view the cursor is in.
+    pub fn resize_edges_at(cursor: &mut Cursor, view: &::View) -> Edges {
+        let (lx, ly) = cursor.coords();
+        let Origin { x, y } = view.origin.get();
+        let Size { width, height } = view.get_size();
+        let horizontal = if lx < x as f64 + width as f64 / 2.0 {
+            Edges::WLR_EDGE_LEFT
+        } else {
+            Edges::WLR_EDGE_RIGHT
+        };
+        let vertical = if ly < y as f64 + height as f64 / 2.0 {
+            Edges::WLR_EDGE_TOP
+        } else {
+            Edges::WLR_EDGE_BOTTOM
+        };
+        horizontal | vertical
+    }
+
+    /// Finds the view at a point of the layout, and the surface of it there.
+    pub fn view_at(views: &mut [Rc<::View>],
+                   lx: f64,
+                   ly: f64)
+                   -> (Option<Rc<::View>>, Option<SurfaceHandle>, f64, f64) {
+        for view in views.iter().filter(|view| !view.minimized.get()) {
             match view.shell {
                 ::Shell::XdgV6(ref shell) => {
                     let (mut sx, mut sy) = (0.0, 0.0);
                     let surface = dehandle!(
                         @shell = {shell};
-                        let (lx, ly) = cursor.coords();
                         let Origin {x: shell_x, y: shell_y} = view.origin.get();
                         let (view_sx, view_sy) = (lx - shell_x as f64, ly - shell_y as f64);
                         shell.surface_at(view_sx, view_sy, &mut sx, &mut sy)
@@ -147,16 +389,237 @@ impl Seat {
                         return (Some(view.clone()), surface, sx, sy)
                     }
                 }
+                ::Shell::Xdg(ref shell) => {
+                    let (mut sx, mut sy) = (0.0, 0.0);
+                    let surface = dehandle!(
+                        @shell = {shell};
+                        let Origin {x: shell_x, y: shell_y} = view.origin.get();
+                        let (view_sx, view_sy) = (lx - shell_x as f64, ly - shell_y as f64);
+                        shell.surface_at(view_sx, view_sy, &mut sx, &mut sy)
+                    );
+                    if surface.is_some() {
+                        return (Some(view.clone()), surface, sx, sy)
+                    }
+                }
+                ::Shell::XWayland(_) => {
+                    let surface = view.surface();
+                    let (width, height) = with_handles!([(surface: {&surface})] => {
+                        surface.current_state().size()
+                    }).unwrap();
+                    let Origin { x: shell_x, y: shell_y } = view.origin.get();
+                    let (sx, sy) = (lx - shell_x as f64, ly - shell_y as f64);
+                    if sx >= 0.0 && sy >= 0.0 && sx < width as f64 && sy < height as f64 {
+                        return (Some(view.clone()), Some(surface), sx, sy)
+                    }
+                }
+            }
+            // The border belongs to the view, but there's no surface there.
+            if !Seat::border_edges_at(lx, ly, view).is_empty() {
+                return (Some(view.clone()), None, 0.0, 0.0)
             }
         }
         (None, None, 0.0, 0.0)
     }
 
+    /// The edges of the border of the view that a point is on, which are
+    /// empty if it isn't on the border.
+    pub fn border_edges_at(lx: f64, ly: f64, view: &::View) -> Edges {
+        let border_width = view.border_width() as f64;
+        let mut edges = Edges::empty();
+        if border_width <= 0.0 {
+            return edges
+        }
+        let Origin { x, y } = view.origin.get();
+        let Size { width, height } = view.get_size();
+        let (x, y, width, height) = (x as f64, y as f64, width as f64, height as f64);
+        let in_outer = lx >= x - border_width && lx < x + width + border_width &&
+                       ly >= y - border_width && ly < y + height + border_width;
+        if !in_outer {
+            return edges
+        }
+        if lx < x {
+            edges |= Edges::WLR_EDGE_LEFT;
+        } else if lx >= x + width {
+            edges |= Edges::WLR_EDGE_RIGHT;
+        }
+        if ly < y {
+            edges |= Edges::WLR_EDGE_TOP;
+        } else if ly >= y + height {
+            edges |= Edges::WLR_EDGE_BOTTOM;
+        }
+        edges
+    }
+
+    /// Finds the first layer surface of the given layers at a point of the
+    /// layout.
+    pub fn layer_surface_at(layer_surfaces: &[Rc<::LayerSurface>],
+                            layers: &[Layer],
+                            lx: f64,
+                            ly: f64)
+                            -> Option<(SurfaceHandle, f64, f64)> {
+        for layer in layers {
+            for layer_surface in layer_surfaces.iter()
+                                               .filter(|l| l.layer == *layer)
+                                               .filter(|l| l.mapped.get())
+            {
+                let Area { origin: Origin { x, y },
+                           size: Size { width, height } } = layer_surface.geometry.get();
+                let (sx, sy) = (lx - x as f64, ly - y as f64);
+                if sx >= 0.0 && sy >= 0.0 && sx < width as f64 && sy < height as f64 {
+                    return Some((layer_surface.surface(), sx, sy))
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds the surface at a point of the layout, respecting the stacking
+    /// order of the layers and the views.
+    ///
+    /// The view is the one the surface belongs to, if it isn't a layer
+    /// surface.
+    pub fn surface_at(views: &mut [Rc<::View>],
+                      layer_surfaces: &[Rc<::LayerSurface>],
+                      lx: f64,
+                      ly: f64)
+                      -> (Option<Rc<::View>>, Option<SurfaceHandle>, f64, f64) {
+        let (fullscreen, views) = ::split_fullscreen(views);
+        let overlay = Seat::layer_surface_at(layer_surfaces, &[Layer::Overlay], lx, ly);
+        if let Some((surface, sx, sy)) = overlay {
+            return (None, Some(surface), sx, sy)
+        }
+        if let (Some(view), surface, sx, sy) = Seat::view_at(fullscreen, lx, ly) {
+            return (Some(view), surface, sx, sy)
+        }
+        let top = Seat::layer_surface_at(layer_surfaces, &[Layer::Top], lx, ly);
+        if let Some((surface, sx, sy)) = top {
+            return (None, Some(surface), sx, sy)
+        }
+        if let (Some(view), surface, sx, sy) = Seat::view_at(views, lx, ly) {
+            return (Some(view), surface, sx, sy)
+        }
+        let below = Seat::layer_surface_at(layer_surfaces,
+                                           &[Layer::Bottom, Layer::Background],
+                                           lx,
+                                           ly);
+        match below {
+            Some((surface, sx, sy)) => (None, Some(surface), sx, sy),
+            None => (None, None, 0.0, 0.0)
+        }
+    }
+
+    /// Puts a touch point down on the surface at a point of the layout,
+    /// focusing the view it belongs to.
+    pub fn touch_down(&mut self,
+                      cursor: &Cursor,
+                      views: &mut Vec<Rc<::View>>,
+                      layer_surfaces: &[Rc<::LayerSurface>],
+                      touch_id: i32,
+                      lx: f64,
+                      ly: f64,
+                      time_msec: u32) {
+        let (view, surface, sx, sy) = Seat::surface_at(views, layer_surfaces, lx, ly);
+        if let Some(view) = view {
+            self.focus_view(view, views);
+        }
+        let surface = match surface {
+            Some(surface) => surface,
+            None => return
+        };
+        let client = with_handles!([(surface: {&surface})] => {
+            unsafe {
+                let resource = (*surface.as_ptr()).resource as *mut wl_resource;
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, resource)
+            }
+        }).unwrap_or(ptr::null_mut());
+        self.touch_points.insert(touch_id,
+                                 TouchPoint { position: (lx, ly),
+                                              surface_origin: (lx - sx, ly - sy),
+                                              client });
+        dehandle!(
+            @seat = {&self.seat};
+            @surface = {surface};
+            seat.touch_notify_down(surface,
+                                   Duration::from_millis(time_msec as u64),
+                                   touch_id,
+                                   sx,
+                                   sy);
+        );
+        for drag_icon in &self.drag_icons {
+            drag_icon.update_position(cursor, &self.touch_points);
+        }
+    }
+
+    /// Moves a touch point, which stays on the surface it went down on.
+    pub fn touch_motion(&mut self,
+                        cursor: &Cursor,
+                        touch_id: i32,
+                        lx: f64,
+                        ly: f64,
+                        time_msec: u32) {
+        let (origin_x, origin_y) = match self.touch_points.get_mut(&touch_id) {
+            Some(point) => {
+                point.position = (lx, ly);
+                point.surface_origin
+            }
+            None => return
+        };
+        dehandle!(
+            @seat = {&self.seat};
+            seat.touch_notify_motion(Duration::from_millis(time_msec as u64),
+                                     touch_id,
+                                     lx - origin_x,
+                                     ly - origin_y)
+        );
+        for drag_icon in &self.drag_icons {
+            drag_icon.update_position(cursor, &self.touch_points);
+        }
+    }
+
+    /// Lifts a touch point from its surface.
+    pub fn touch_up(&mut self, touch_id: i32, time_msec: u32) {
+        if self.touch_points.remove(&touch_id).is_some() {
+            dehandle!(
+                @seat = {&self.seat};
+                seat.touch_notify_up(Duration::from_millis(time_msec as u64), touch_id)
+            );
+        }
+    }
+
+    /// Cancels a touch point, e.g because the device recognized a gesture.
+    ///
+    /// Clients can only cancel all of their points at once, so every point
+    /// of the client that has this one goes with it.
+    pub fn touch_cancel(&mut self, touch_id: i32, time_msec: u32) {
+        let client = match self.touch_points.get(&touch_id) {
+            Some(point) => point.client,
+            None => return
+        };
+        let cancelled: Vec<i32> = self.touch_points
+                                      .iter()
+                                      .filter(|&(_, point)| point.client == client)
+                                      .map(|(&touch_id, _)| touch_id)
+                                      .collect();
+        for touch_id in &cancelled {
+            self.touch_points.remove(touch_id);
+        }
+        with_handles!([(seat: {&self.seat})] => {
+            unsafe {
+                send_touch_cancel(seat.as_ptr(), client);
+                forget_touch_points(seat.as_ptr(), &cancelled, time_msec);
+            }
+        }).unwrap_or(())
+    }
+
     pub fn update_cursor_position(&mut self,
                                   cursor: &mut Cursor,
                                   xcursor_manager: &mut XCursorManager,
                                   views: &mut [Rc<::View>],
+                                  layer_surfaces: &[Rc<::LayerSurface>],
                                   time_msec: Option<u32>) {
+        for drag_icon in &self.drag_icons {
+            drag_icon.update_position(cursor, &self.touch_points);
+        }
         let time = if let Some(time_msec) = time_msec {
             Duration::from_millis(time_msec as u64)
         } else {
@@ -165,6 +628,7 @@ impl Seat {
 
         match self.action {
             Some(Action::Moving { start }) => {
+                self.set_cursor_image("grabbing", cursor, xcursor_manager);
                 self.focused = self.focused.take().map(|f| {
                                                            self.move_view(cursor, &f, start);
                                                            f
@@ -174,6 +638,7 @@ impl Seat {
                                     start,
                                     original_size,
                                     edges }) => {
+                self.set_cursor_image(::resize_cursor_image(edges), cursor, xcursor_manager);
                 self.focused = self.focused.take().map(|view| {
                     let (cursor_lx, cursor_ly) = cursor.coords();
                     let Origin { x: offs_x,
@@ -206,7 +671,8 @@ impl Seat {
                 });
             }
             _ => {
-                let (_view, surface, sx, sy) = Seat::view_at_pointer(views, cursor);
+                let (lx, ly) = cursor.coords();
+                let (_, surface, sx, sy) = Seat::surface_at(views, layer_surfaces, lx, ly);
                 match surface {
                     Some(surface) => {
                         dehandle!(
@@ -217,10 +683,7 @@ impl Seat {
                         );
                     }
                     None => {
-                        if self.has_client_cursor {
-                            xcursor_manager.set_cursor_image("left_ptr".to_string(), cursor);
-                            self.has_client_cursor = false;
-                        }
+                        self.set_cursor_image("left_ptr", cursor, xcursor_manager);
                         dehandle!(
                             @seat = {&self.seat};
                             seat.pointer_clear_focus()
@@ -234,19 +697,61 @@ impl Seat {
 
 struct DragIconHandler;
 
+impl DragIconHandler {
+    /// Sets whether the icon is mapped and moves it to the cursor.
+    fn set_mapped(&mut self,
+                  compositor: CompositorHandle,
+                  drag_icon: DragIconHandle,
+                  mapped: bool) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref seat,
+                           ref cursor,
+                           .. } = *server;
+            if let Some(icon) = seat.drag_icons.iter().find(|icon| icon.handle == drag_icon) {
+                icon.mapped.set(mapped);
+                with_handles!([(cursor: {cursor})] => {
+                    icon.update_position(cursor, &seat.touch_points);
+                }).unwrap();
+            }
+        }).unwrap();
+    }
+}
+
 impl wlroots::DragIconHandler for DragIconHandler {
-    fn on_map(&mut self, _: CompositorHandle, _: DragIconHandle) {
-        // TODO damage the drag icon surface location
+    fn on_map(&mut self, compositor: CompositorHandle, drag_icon: DragIconHandle) {
+        self.set_mapped(compositor, drag_icon, true);
     }
 
-    fn on_unmap(&mut self, _: CompositorHandle, _: DragIconHandle) {
-        // TODO damage the drag icon surface location
+    fn on_unmap(&mut self, compositor: CompositorHandle, drag_icon: DragIconHandle) {
+        self.set_mapped(compositor, drag_icon, false);
     }
 
     fn destroyed(&mut self, compositor: CompositorHandle, drag_icon: DragIconHandle) {
         with_handles!([(compositor: {compositor})] => {
             let server: &mut ::Server = compositor.into();
-            server.seat.drag_icons.remove(&DragIcon{ handle: drag_icon });
+            let drag_icons = &mut server.seat.drag_icons;
+            if let Some(index) = drag_icons.iter().position(|icon| icon.handle == drag_icon) {
+                let icon = drag_icons.remove(index);
+                damage::damage_area(icon.extents.get());
+            }
+        }).unwrap();
+    }
+}
+
+impl SurfaceHandler for DragIconHandler {
+    fn on_commit(&mut self, compositor: CompositorHandle, surface: SurfaceHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref seat,
+                           ref cursor,
+                           .. } = *server;
+            // The icon may have been resized or offset.
+            if let Some(icon) = seat.drag_icons.iter().find(|icon| icon.surface() == surface) {
+                with_handles!([(cursor: {cursor})] => {
+                    icon.update_position(cursor, &seat.touch_points);
+                }).unwrap();
+            }
         }).unwrap();
     }
 }
@@ -262,10 +767,13 @@ impl SeatHandler for SeatManager {
                              ref mut seat,
                 .. } = *server;
                 @cursor = {cursor};
-                let (hotspot_x, hotspot_y) = event.location();
-                let surface = &*surface;
-                cursor.set_surface(Some(surface), hotspot_x, hotspot_y);
-                seat.has_client_cursor = true
+                // The compositor's image stays while it moves or resizes.
+                if seat.action.is_none() {
+                    let (hotspot_x, hotspot_y) = event.location();
+                    let surface = &*surface;
+                    cursor.set_surface(Some(surface), hotspot_x, hotspot_y);
+                    seat.cursor_image = None;
+                }
             );
         }
     }
@@ -278,9 +786,9 @@ impl SeatHandler for SeatManager {
         with_handles!([(compositor: {compositor})] => {
             let server: &mut ::Server = compositor.into();
             let ::Server { ref mut seat, .. } = *server;
-            seat.drag_icons.insert(DragIcon { handle: drag_icon });
+            seat.drag_icons.push(DragIcon::new(drag_icon));
         }).unwrap();
-        (Some(Box::new(DragIconHandler)), None)
+        (Some(Box::new(DragIconHandler)), Some(Box::new(DragIconHandler)))
     }
 }
 
@@ -289,3 +797,63 @@ impl SeatManager {
         SeatManager::default()
     }
 }
+
+/// Sends `wl_touch.cancel` to every `wl_touch` the client has for the seat.
+///
+/// wlroots has no API for it, the resources are in the `touches` list of
+/// the `wlr_seat_client`.
+unsafe fn send_touch_cancel(seat: *mut wlr_seat, client: *mut wl_client) {
+    if client.is_null() {
+        return
+    }
+    let seat_client = wlr_seat_client_for_wl_client(seat, client as *mut _);
+    if seat_client.is_null() {
+        return
+    }
+    let touches = &mut (*seat_client).touches as *mut _ as *mut wl_list;
+    let mut link = (*touches).next;
+    while link != touches {
+        let resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_from_link, link);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      resource,
+                      TOUCH_CANCEL_EVENT);
+        link = (*link).next;
+    }
+}
+
+/// Makes wlroots forget touch points that were cancelled.
+///
+/// wlroots only drops a point when it goes up, and its default grab sends
+/// `up` to the client, which mustn't get any event for the point anymore.
+/// A grab that is already active, like a drag, doesn't send it either.
+unsafe fn forget_touch_points(seat: *mut wlr_seat, touch_ids: &[i32], time_msec: u32) {
+    if wlr_seat_touch_has_grab(seat) {
+        for &touch_id in touch_ids {
+            wlr_seat_touch_notify_up(seat, time_msec, touch_id);
+        }
+        return
+    }
+    let mut grab = wlr_seat_touch_grab { interface: &CANCELLED_GRAB_INTERFACE,
+                                         seat,
+                                         data: ptr::null_mut() };
+    wlr_seat_touch_start_grab(seat, &mut grab);
+    for &touch_id in touch_ids {
+        wlr_seat_touch_notify_up(seat, time_msec, touch_id);
+    }
+    wlr_seat_touch_end_grab(seat);
+}
+
+unsafe extern "C" fn cancelled_grab_down(_: *mut wlr_seat_touch_grab,
+                                         _: u32,
+                                         _: *mut wlr_touch_point)
+                                         -> u32 {
+    0
+}
+
+unsafe extern "C" fn cancelled_grab_point(_: *mut wlr_seat_touch_grab,
+                                          _: u32,
+                                          _: *mut wlr_touch_point) {
+}
+
+unsafe extern "C" fn cancelled_grab_cancel(_: *mut wlr_seat_touch_grab) {}
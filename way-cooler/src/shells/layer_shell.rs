@@ -0,0 +1,323 @@
+//! Support for wlr-layer-shell, used by panels, docks, launchers and
+//! wallpapers.
+//!
+//! Layer surfaces are not views. They are anchored to an output and are
+//! stacked in one of four layers, two below and two above the views.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use output::damage;
+use wlroots::layer_shell::Anchor;
+use wlroots::{Area, CompositorHandle, Layer, LayerShellManagerHandler, LayerSurfaceHandle,
+              LayerSurfaceHandler, Origin, Output, OutputHandle, OutputLayout, Size,
+              SurfaceHandle, SurfaceHandler};
+
+/// The order in which layers are arranged, from the top down.
+const ARRANGE_ORDER: [Layer; 4] = [Layer::Overlay, Layer::Top, Layer::Bottom, Layer::Background];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerSurface {
+    pub layer_surface: LayerSurfaceHandle,
+    pub output: OutputHandle,
+    pub layer: Layer,
+    /// Where the surface is placed, in layout coordinates.
+    ///
+    /// This is only valid after the output has been arranged.
+    pub geometry: Cell<Area>,
+    /// Whether the surface is mapped. Unmapped surfaces are not shown, don't
+    /// get input and don't reserve their exclusive zone.
+    pub mapped: Cell<bool>
+}
+
+impl LayerSurface {
+    pub fn new(layer_surface: LayerSurfaceHandle, output: OutputHandle, layer: Layer) -> Self {
+        LayerSurface { layer_surface,
+                       output,
+                       layer,
+                       geometry: Cell::new(Area::default()),
+                       mapped: Cell::new(false) }
+    }
+
+    pub fn surface(&self) -> SurfaceHandle {
+        with_handles!([(layer_surface: {&self.layer_surface})] => {
+            layer_surface.surface()
+        }).unwrap()
+    }
+
+    /// Whether the surface wants keyboard focus while it is mapped.
+    pub fn keyboard_interactive(&self) -> bool {
+        with_handles!([(layer_surface: {&self.layer_surface})] => {
+            layer_surface.current().keyboard_interactive()
+        }).unwrap_or(false)
+    }
+
+    pub fn for_each_surface(&self, f: &mut FnMut(SurfaceHandle, i32, i32)) {
+        with_handles!([(layer_surface: {&self.layer_surface})] => {
+            layer_surface.for_each_surface(f);
+        }).unwrap();
+    }
+
+    /// Places the surface within the bounds of the output and sends it the
+    /// new size.
+    ///
+    /// Only surfaces whose exclusivity matches `exclusive` are placed, so
+    /// that exclusive zones are reserved before the others are placed.
+    fn arrange(&self, full_area: Area, usable_area: &mut Area, exclusive: bool) {
+        with_handles!([(layer_surface: {&self.layer_surface})] => {
+            let state = layer_surface.current();
+            let exclusive_zone = if self.mapped.get() { state.exclusive_zone() } else { 0 };
+            if exclusive != (exclusive_zone > 0) {
+                return
+            }
+            let bounds = if exclusive_zone == -1 { full_area } else { *usable_area };
+            let anchor = state.anchor();
+            let (margin_top, margin_right, margin_bottom, margin_left) = state.margin();
+            let (margin_top, margin_right, margin_bottom, margin_left) =
+                (margin_top as i32, margin_right as i32, margin_bottom as i32, margin_left as i32);
+            let (desired_width, desired_height) = state.desired_size();
+            let Size { mut width, mut height } =
+                Size::new(desired_width as i32, desired_height as i32);
+            let (x, y);
+
+            let horizontal = Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_LEFT |
+                             Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_RIGHT;
+            if width == 0 && anchor.contains(horizontal) {
+                x = bounds.origin.x + margin_left;
+                width = bounds.size.width - margin_left - margin_right;
+            } else if anchor.contains(Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_LEFT) {
+                x = bounds.origin.x + margin_left;
+            } else if anchor.contains(Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_RIGHT) {
+                x = bounds.origin.x + bounds.size.width - width - margin_right;
+            } else {
+                x = bounds.origin.x + bounds.size.width / 2 - width / 2;
+            }
+
+            let vertical = Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_TOP |
+                           Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_BOTTOM;
+            if height == 0 && anchor.contains(vertical) {
+                y = bounds.origin.y + margin_top;
+                height = bounds.size.height - margin_top - margin_bottom;
+            } else if anchor.contains(Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_TOP) {
+                y = bounds.origin.y + margin_top;
+            } else if anchor.contains(Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_BOTTOM) {
+                y = bounds.origin.y + bounds.size.height - height - margin_bottom;
+            } else {
+                y = bounds.origin.y + bounds.size.height / 2 - height / 2;
+            }
+
+            if width <= 0 || height <= 0 {
+                warn!("Closing layer surface with an invalid size {}x{}", width, height);
+                layer_surface.close();
+                return
+            }
+
+            if exclusive_zone > 0 {
+                let margins = (margin_top, margin_right, margin_bottom, margin_left);
+                apply_exclusive_zone(usable_area, anchor, exclusive_zone, margins);
+            }
+
+            let geometry = Area::new(Origin::new(x, y), Size::new(width, height));
+            if self.geometry.get() != geometry {
+                damage::damage_area(self.geometry.get());
+                damage::damage_area(geometry);
+            }
+            self.geometry.set(geometry);
+            layer_surface.configure(width as u32, height as u32);
+        }).unwrap_or(())
+    }
+}
+
+/// Shrinks the usable area of an output by the exclusive zone of a surface.
+///
+/// The zone is only honored if the surface is anchored to a single edge, or
+/// to an edge and both of the edges perpendicular to it.
+fn apply_exclusive_zone(usable_area: &mut Area,
+                        anchor: Anchor,
+                        exclusive_zone: i32,
+                        margins: (i32, i32, i32, i32)) {
+    let (margin_top, margin_right, margin_bottom, margin_left) = margins;
+    let top = Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_TOP;
+    let bottom = Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_BOTTOM;
+    let left = Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_LEFT;
+    let right = Anchor::ZWLR_LAYER_SURFACE_V1_ANCHOR_RIGHT;
+    let Area { ref mut origin,
+               ref mut size } = *usable_area;
+    if anchor == top || anchor == top | left | right {
+        origin.y += exclusive_zone + margin_top;
+        size.height -= exclusive_zone + margin_top;
+    } else if anchor == bottom || anchor == bottom | left | right {
+        size.height -= exclusive_zone + margin_bottom;
+    } else if anchor == left || anchor == left | top | bottom {
+        origin.x += exclusive_zone + margin_left;
+        size.width -= exclusive_zone + margin_left;
+    } else if anchor == right || anchor == right | top | bottom {
+        size.width -= exclusive_zone + margin_right;
+    }
+}
+
+/// Places all the layer surfaces of an output and updates the usable area
+/// of that output.
+pub fn arrange_layers(output: &mut Output,
+                      layout: &mut OutputLayout,
+                      layer_surfaces: &[Rc<LayerSurface>],
+                      usable_areas: &mut HashMap<OutputHandle, Area>) {
+    let output_handle = output.weak_reference();
+    let full_area = layout.get_box(output);
+    let mut usable_area = full_area;
+    // Exclusive surfaces go first, the rest use whatever space is left over.
+    for &exclusive in &[true, false] {
+        for layer in ARRANGE_ORDER.iter() {
+            for layer_surface in layer_surfaces.iter()
+                                               .filter(|l| l.output == output_handle)
+                                               .filter(|l| l.layer == *layer)
+            {
+                layer_surface.arrange(full_area, &mut usable_area, exclusive);
+            }
+        }
+    }
+    usable_areas.insert(output_handle, usable_area);
+}
+
+/// Arranges the output of a layer surface, if it still exists.
+fn arrange_output_of(server: &mut ::Server, layer_surface: &LayerSurface) {
+    let ::Server { ref mut layout,
+                   ref layer_surfaces,
+                   ref mut usable_areas,
+                   .. } = *server;
+    with_handles!([(layout: {layout}), (output: {&layer_surface.output})] => {
+        arrange_layers(output, layout, layer_surfaces, usable_areas);
+    }).unwrap_or(())
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LayerShell;
+
+impl LayerSurfaceHandler for LayerShell {
+    fn on_commit(&mut self,
+                 compositor: CompositorHandle,
+                 _: SurfaceHandle,
+                 layer_surface: LayerSurfaceHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let committed = server.layer_surfaces
+                                  .iter()
+                                  .find(|l| l.layer_surface == layer_surface)
+                                  .cloned();
+            if let Some(committed) = committed {
+                arrange_output_of(server, &committed);
+                damage::damage_area(committed.geometry.get());
+            }
+        }).unwrap();
+    }
+
+    fn on_map(&mut self, compositor: CompositorHandle, layer_surface: LayerSurfaceHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let mapped = server.layer_surfaces
+                               .iter()
+                               .find(|l| l.layer_surface == layer_surface)
+                               .cloned();
+            if let Some(mapped) = mapped {
+                mapped.mapped.set(true);
+                arrange_output_of(server, &mapped);
+                damage::damage_area(mapped.geometry.get());
+                let above_views = mapped.layer == Layer::Top || mapped.layer == Layer::Overlay;
+                if above_views && mapped.keyboard_interactive() {
+                    server.seat.focus_layer_surface(mapped);
+                }
+            }
+        }).unwrap();
+    }
+
+    fn on_unmap(&mut self, compositor: CompositorHandle, layer_surface: LayerSurfaceHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let unmapped = server.layer_surfaces
+                                 .iter()
+                                 .find(|l| l.layer_surface == layer_surface)
+                                 .cloned();
+            if let Some(unmapped) = unmapped {
+                unmap_layer_surface(server, &unmapped);
+            }
+        }).unwrap();
+    }
+
+    fn destroyed(&mut self, compositor: CompositorHandle, layer_surface: LayerSurfaceHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            remove_layer_surface(server, &layer_surface);
+        }).unwrap();
+    }
+}
+
+/// Hides the layer surface, giving back its space and the keyboard focus if
+/// it had it.
+///
+/// The surface is still tracked, it can be mapped again.
+fn unmap_layer_surface(server: &mut ::Server, layer_surface: &Rc<LayerSurface>) {
+    if !layer_surface.mapped.get() {
+        return
+    }
+    layer_surface.mapped.set(false);
+    damage::damage_area(layer_surface.geometry.get());
+    arrange_output_of(server, layer_surface);
+
+    let ::Server { ref mut seat,
+                   ref mut views,
+                   .. } = *server;
+    if seat.focused_layer.as_ref() == Some(layer_surface) {
+        seat.focused_layer = None;
+        seat.focus_next_view(views);
+    }
+}
+
+/// Stops tracking the layer surface, unmapping it first if it still was.
+fn remove_layer_surface(server: &mut ::Server, layer_surface: &LayerSurfaceHandle) {
+    let pos = match server.layer_surfaces
+                          .iter()
+                          .position(|l| l.layer_surface == *layer_surface)
+    {
+        Some(pos) => pos,
+        None => return
+    };
+    let removed = server.layer_surfaces[pos].clone();
+    unmap_layer_surface(server, &removed);
+    server.layer_surfaces.remove(pos);
+}
+
+pub struct LayerShellManager;
+
+impl LayerShellManagerHandler for LayerShellManager {
+    fn new_surface(&mut self,
+                   compositor: CompositorHandle,
+                   layer_surface: LayerSurfaceHandle)
+                   -> (Option<Box<LayerSurfaceHandler>>, Option<Box<SurfaceHandler>>) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let placement = with_handles!([(shell: {&layer_surface})] => {
+                let output = match shell.output() {
+                    Some(output) => Some(output),
+                    // The client lets us choose, so put it on the first output.
+                    None => server.outputs.first().cloned().map(|output| {
+                        shell.set_output(&output);
+                        output
+                    })
+                };
+                if output.is_none() {
+                    warn!("Closing layer surface because there are no outputs");
+                    shell.close();
+                }
+                output.map(|output| (output, shell.layer()))
+            }).unwrap();
+            if let Some((output, layer)) = placement {
+                let new_surface = Rc::new(LayerSurface::new(layer_surface.clone(), output, layer));
+                server.layer_surfaces.push(new_surface.clone());
+                // wlroots expects a configure in response to the first commit.
+                arrange_output_of(server, &new_surface);
+            }
+        }).unwrap();
+        (Some(Box::new(LayerShell)), None)
+    }
+}
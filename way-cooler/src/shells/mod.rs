@@ -1,13 +1,19 @@
+mod layer_shell;
+mod xdg;
 mod xdg_v6;
 
+pub use self::layer_shell::*;
+pub use self::xdg::*;
 pub use self::xdg_v6::*;
 
-use wlroots::{Area, HandleResult, SurfaceHandle, XdgV6ShellSurfaceHandle};
+use wlroots::{Area, HandleResult, Origin, Size, SurfaceHandle, XWaylandSurfaceHandle,
+              XdgShellSurfaceHandle, XdgV6ShellSurfaceHandle};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Shell {
-    XdgV6(XdgV6ShellSurfaceHandle) /* TODO WlShell
-                                    * TODO Xdg */
+    XdgV6(XdgV6ShellSurfaceHandle),
+    Xdg(XdgShellSurfaceHandle),
+    XWayland(XWaylandSurfaceHandle) /* TODO WlShell */
 }
 
 impl Shell {
@@ -18,13 +24,31 @@ impl Shell {
                 shell.run(|shell| shell.surface())
                      .expect("An xdg v6 client did not provide us a surface")
             }
+            Shell::Xdg(ref mut shell) => {
+                shell.run(|shell| shell.surface())
+                     .expect("An xdg client did not provide us a surface")
+            }
+            Shell::XWayland(ref mut shell) => {
+                shell.run(|shell| shell.surface())
+                     .expect("An XWayland client was destroyed")
+                     .expect("An XWayland client did not provide us a surface")
+            }
         }
     }
 
     /// Get the geometry of a shell.
     pub fn geometry(&mut self) -> HandleResult<Area> {
         match *self {
-            Shell::XdgV6(ref mut shell) => shell.run(|shell| shell.geometry())
+            Shell::XdgV6(ref mut shell) => shell.run(|shell| shell.geometry()),
+            Shell::Xdg(ref mut shell) => shell.run(|shell| shell.geometry()),
+            Shell::XWayland(ref mut shell) => {
+                shell.run(|shell| {
+                              let (x, y) = shell.coords();
+                              let (width, height) = shell.dimensions();
+                              Area::new(Origin::new(x as i32, y as i32),
+                                        Size::new(width as i32, height as i32))
+                          })
+            }
         }
     }
 }
@@ -34,3 +58,15 @@ impl Into<Shell> for XdgV6ShellSurfaceHandle {
         Shell::XdgV6(self)
     }
 }
+
+impl Into<Shell> for XdgShellSurfaceHandle {
+    fn into(self) -> Shell {
+        Shell::Xdg(self)
+    }
+}
+
+impl Into<Shell> for XWaylandSurfaceHandle {
+    fn into(self) -> Shell {
+        Shell::XWayland(self)
+    }
+}
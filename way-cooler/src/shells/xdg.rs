@@ -0,0 +1,305 @@
+use wlroots::{Area, CompositorHandle, Origin, SurfaceHandle, SurfaceHandler, XdgShellHandler,
+              XdgShellManagerHandler, XdgShellState::*, XdgShellSurfaceHandle};
+
+use output::damage;
+use protocols::{pointer_constraints, window_management};
+use std::rc::Rc;
+use wlroots::xdg_shell_events::{MoveEvent, ResizeEvent, SetFullscreenEvent};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Xdg {
+    shell_surface: XdgShellSurfaceHandle,
+    /// The shell of the view a popup belongs to.
+    ///
+    /// Popups aren't views, they are drawn and damaged as part of that view.
+    popup_parent: Option<::Shell>
+}
+
+impl Xdg {
+    pub fn new() -> Self {
+        Xdg { ..Xdg::default() }
+    }
+
+    pub fn new_popup(parent: ::Shell) -> Self {
+        Xdg { popup_parent: Some(parent),
+              ..Xdg::default() }
+    }
+
+    /// Damages the view of the popup, which includes the popup itself.
+    fn damage_popup_parent(&self, views: &[Rc<::View>]) {
+        if let Some(ref parent) = self.popup_parent {
+            if let Some(view) = views.iter().find(|view| view.shell == *parent) {
+                damage::damage_view(view);
+            }
+        }
+    }
+}
+
+impl XdgShellHandler for Xdg {
+    fn resize_request(&mut self,
+                      compositor: CompositorHandle,
+                      _: SurfaceHandle,
+                      shell_surface: XdgShellSurfaceHandle,
+                      event: &ResizeEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref mut seat,
+                         ref mut views,
+                         ref mut cursor,
+                         .. } = *server;
+            let resizing_shell = shell_surface.into();
+
+            if let Some(view) = views.iter().find(|view| view.shell == resizing_shell).cloned() {
+                seat.begin_resize(cursor, view.clone(), views, event.edges())
+            }
+        }).unwrap();
+    }
+
+    fn move_request(&mut self,
+                    compositor: CompositorHandle,
+                    _: SurfaceHandle,
+                    shell_surface: XdgShellSurfaceHandle,
+                    _: &MoveEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ref mut seat = server.seat;
+            let ref mut cursor = server.cursor;
+
+            if let Some(ref mut view) = seat.focused {
+                let shell: ::Shell = shell_surface.into();
+                let action = &mut seat.action;
+                if view.shell == shell {
+                    with_handles!([(cursor: {cursor})] => {
+                        let (lx, ly) = cursor.coords();
+                        let Origin { x: shell_x, y: shell_y } = view.origin.get();
+                        let (view_sx, view_sy) = (lx - shell_x as f64, ly - shell_y as f64);
+                        let start = Origin::new(view_sx as _, view_sy as _);
+                        *action = Some(::Action::Moving { start: start });
+                    }).unwrap();
+                }
+            }
+        }).unwrap();
+    }
+
+    fn maximize_request(&mut self,
+                        compositor: CompositorHandle,
+                        _: SurfaceHandle,
+                        shell_surface: XdgShellSurfaceHandle) {
+        let maximized = with_handles!([(shell_surface: {&shell_surface})] => {
+            match shell_surface.state() {
+                Some(TopLevel(toplevel)) => toplevel.client_pending_state().maximized,
+                _ => false
+            }
+        }).unwrap_or(false);
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let shell = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == shell).cloned() {
+                server.set_maximized(&view, maximized);
+            }
+        }).unwrap();
+    }
+
+    fn minimize_request(&mut self,
+                        compositor: CompositorHandle,
+                        _: SurfaceHandle,
+                        shell_surface: XdgShellSurfaceHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let shell = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == shell).cloned() {
+                server.set_minimized(&view, true);
+            }
+        }).unwrap();
+    }
+
+    fn fullscreen_request(&mut self,
+                          compositor: CompositorHandle,
+                          _: SurfaceHandle,
+                          shell_surface: XdgShellSurfaceHandle,
+                          event: &SetFullscreenEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let shell = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == shell).cloned() {
+                server.set_fullscreen(&view, event.fullscreen(), event.output());
+            }
+        }).unwrap();
+    }
+
+    fn on_commit(&mut self,
+                 compositor: CompositorHandle,
+                 _: SurfaceHandle,
+                 shell_surface: XdgShellSurfaceHandle) {
+        let configure_serial = {
+            with_handles!([(shell_surface: {shell_surface.clone()})] => {
+                shell_surface.configure_serial()
+            }).unwrap()
+        };
+
+        let surface = shell_surface.into();
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref mut views, .. } = *server;
+
+            if let Some(view) = views.iter().find(|view| view.shell == surface).cloned() {
+                if let Some(move_resize) = view.pending_move_resize.get() {
+                    if move_resize.serial >= configure_serial {
+                        let Origin {mut x, mut y} = view.origin.get();
+                        if move_resize.update_x {
+                            x  = move_resize.area.origin.x + move_resize.area.size.width -
+                                 view.get_size().width;
+                        }
+                        if move_resize.update_y {
+                            y  = move_resize.area.origin.y + move_resize.area.size.height -
+                                 view.get_size().height;
+                        }
+
+                        view.set_origin(Origin { x, y });
+
+                        if move_resize.serial == configure_serial {
+                            view.pending_move_resize.set(None);
+                        }
+                    }
+                }
+                damage::damage_view(&view);
+                window_management::geometry_changed(&view);
+                pointer_constraints::surface_committed(&view.surface());
+            } else {
+                self.damage_popup_parent(views);
+            }
+        }).unwrap();
+    }
+
+    fn map_request(&mut self,
+                   compositor: CompositorHandle,
+                   _: SurfaceHandle,
+                   shell_surface_handle: XdgShellSurfaceHandle) {
+        // Requests to be maximized or fullscreen from before it was mapped.
+        let pending_state = with_handles!([(shell_surface: {&shell_surface_handle})] => {
+            match shell_surface.state().unwrap() {
+                TopLevel(toplevel) => {
+                    let pending = toplevel.client_pending_state();
+                    Some((pending.maximized, pending.fullscreen))
+                }
+                _ => None
+            }
+        }).unwrap();
+        let is_toplevel = pending_state.is_some();
+        let mapped_shell: ::Shell = shell_surface_handle.clone().into();
+        dehandle!(
+            @compositor = {compositor.clone()};
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref mut seat,
+                         ref mut views,
+                         ref layer_surfaces,
+                         ref cursor,
+                         ref mut xcursor_manager,
+                         .. } = *server;
+            if is_toplevel {
+                let view = Rc::new(::View::new(mapped_shell.clone()));
+                views.push(view.clone());
+                damage::damage_view(&view);
+                window_management::view_mapped(&view);
+                seat.focus_view(view, views);
+            } else {
+                self.damage_popup_parent(views);
+            };
+            @cursor = {cursor};
+            seat.update_cursor_position(cursor, xcursor_manager, views, layer_surfaces, None)
+        );
+        if let Some((maximized, fullscreen)) = pending_state {
+            with_handles!([(compositor: {compositor})] => {
+                let server: &mut ::Server = compositor.into();
+                let view = server.views.iter().find(|view| view.shell == mapped_shell).cloned();
+                if let Some(view) = view {
+                    server.set_initial_state(&view, maximized, fullscreen);
+                }
+            }).unwrap();
+        }
+    }
+
+    fn unmap_request(&mut self,
+                     compositor: CompositorHandle,
+                     _: SurfaceHandle,
+                     shell_surface: XdgShellSurfaceHandle) {
+        dehandle!(
+            @compositor = {compositor};
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref mut seat,
+                         ref mut views,
+                         ref layer_surfaces,
+                         ref cursor,
+                         ref mut xcursor_manager,
+                         .. } = *server;
+            let destroyed_shell = shell_surface.into();
+            if let Some(pos) = views.iter().position(|view| view.shell == destroyed_shell) {
+                let view = views.remove(pos);
+                damage::damage_view(&view);
+                window_management::view_unmapped(&view);
+            } else {
+                self.damage_popup_parent(views);
+            };
+
+            seat.focus_next_view(views);
+            @cursor = {cursor};
+            seat.update_cursor_position(cursor, xcursor_manager, views, layer_surfaces, None)
+        );
+    }
+}
+
+pub struct XdgShellManager;
+
+impl XdgShellManagerHandler for XdgShellManager {
+    fn new_surface(&mut self,
+                   compositor: CompositorHandle,
+                   shell_surface: XdgShellSurfaceHandle)
+                   -> (Option<Box<XdgShellHandler>>, Option<Box<SurfaceHandler>>) {
+        let is_popup = with_handles!([(shell_surface: {&shell_surface})] => {
+            match shell_surface.state() {
+                Some(Popup(_)) => true,
+                _ => false
+            }
+        }).unwrap_or(false);
+        if !is_popup {
+            return (Some(Box::new(::Xdg::new())), None)
+        }
+        let parent = with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            unconstrain_popup(server, &shell_surface)
+        }).unwrap_or(None);
+        match parent {
+            Some(parent) => (Some(Box::new(::Xdg::new_popup(parent))), None),
+            None => (Some(Box::new(::Xdg::new())), None)
+        }
+    }
+}
+
+/// Moves a new popup so it stays inside the output of the view it belongs
+/// to, following the rules of its positioner, and returns that view's shell.
+///
+/// The parent of the popup is either the toplevel of a view or another popup
+/// of that view, so the view is the one that has the parent surface.
+fn unconstrain_popup(server: &mut ::Server,
+                     shell_surface: &XdgShellSurfaceHandle)
+                     -> Option<::Shell> {
+    let parent = with_handles!([(shell_surface: {shell_surface})] => {
+        match shell_surface.state() {
+            Some(Popup(popup)) => Some(popup.parent()),
+            _ => None
+        }
+    }).ok()??;
+    let view = server.views.iter().find(|view| view.has_surface(&parent)).cloned()?;
+    let (output_area, _) = server.output_areas_of(&view)?;
+    // The box is relative to the toplevel the popup belongs to.
+    let origin = view.origin.get();
+    let constraint = Area::new(Origin::new(output_area.origin.x - origin.x,
+                                           output_area.origin.y - origin.y),
+                               output_area.size);
+    with_handles!([(shell_surface: {shell_surface})] => {
+        if let Some(Popup(popup)) = shell_surface.state() {
+            popup.unconstrain_from_box(constraint);
+        }
+    }).ok()?;
+    Some(view.shell.clone())
+}
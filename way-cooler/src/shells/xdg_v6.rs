@@ -1,18 +1,38 @@
-use wlroots::{CompositorHandle, Origin, SurfaceHandle, SurfaceHandler, XdgV6ShellHandler,
+use wlroots::{Area, CompositorHandle, Origin, SurfaceHandle, SurfaceHandler, XdgV6ShellHandler,
               XdgV6ShellManagerHandler, XdgV6ShellState::*, XdgV6ShellSurfaceHandle};
 
+use output::damage;
+use protocols::{pointer_constraints, window_management};
 use std::rc::Rc;
-use wlroots::xdg_shell_v6_events::{MoveEvent, ResizeEvent};
+use wlroots::xdg_shell_v6_events::{MoveEvent, ResizeEvent, SetFullscreenEvent};
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct XdgV6 {
-    shell_surface: XdgV6ShellSurfaceHandle
+    shell_surface: XdgV6ShellSurfaceHandle,
+    /// The shell of the view a popup belongs to.
+    ///
+    /// Popups aren't views, they are drawn and damaged as part of that view.
+    popup_parent: Option<::Shell>
 }
 
 impl XdgV6 {
     pub fn new() -> Self {
         XdgV6 { ..XdgV6::default() }
     }
+
+    pub fn new_popup(parent: ::Shell) -> Self {
+        XdgV6 { popup_parent: Some(parent),
+                ..XdgV6::default() }
+    }
+
+    /// Damages the view of the popup, which includes the popup itself.
+    fn damage_popup_parent(&self, views: &[Rc<::View>]) {
+        if let Some(ref parent) = self.popup_parent {
+            if let Some(view) = views.iter().find(|view| view.shell == *parent) {
+                damage::damage_view(view);
+            }
+        }
+    }
 }
 
 impl XdgV6ShellHandler for XdgV6 {
@@ -61,6 +81,52 @@ impl XdgV6ShellHandler for XdgV6 {
         }).unwrap();
     }
 
+    fn maximize_request(&mut self,
+                        compositor: CompositorHandle,
+                        _: SurfaceHandle,
+                        shell_surface: XdgV6ShellSurfaceHandle) {
+        let maximized = with_handles!([(shell_surface: {&shell_surface})] => {
+            match shell_surface.state() {
+                Some(TopLevel(toplevel)) => toplevel.client_pending_state().maximized,
+                _ => false
+            }
+        }).unwrap_or(false);
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let shell = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == shell).cloned() {
+                server.set_maximized(&view, maximized);
+            }
+        }).unwrap();
+    }
+
+    fn minimize_request(&mut self,
+                        compositor: CompositorHandle,
+                        _: SurfaceHandle,
+                        shell_surface: XdgV6ShellSurfaceHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let shell = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == shell).cloned() {
+                server.set_minimized(&view, true);
+            }
+        }).unwrap();
+    }
+
+    fn fullscreen_request(&mut self,
+                          compositor: CompositorHandle,
+                          _: SurfaceHandle,
+                          shell_surface: XdgV6ShellSurfaceHandle,
+                          event: &SetFullscreenEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let shell = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == shell).cloned() {
+                server.set_fullscreen(&view, event.fullscreen(), event.output());
+            }
+        }).unwrap();
+    }
+
     fn on_commit(&mut self,
                  compositor: CompositorHandle,
                  _: SurfaceHandle,
@@ -89,13 +155,18 @@ impl XdgV6ShellHandler for XdgV6 {
                                  view.get_size().height;
                         }
 
-                        view.origin.set(Origin { x, y });
+                        view.set_origin(Origin { x, y });
 
                         if move_resize.serial == configure_serial {
                             view.pending_move_resize.set(None);
                         }
                     }
                 }
+                damage::damage_view(&view);
+                window_management::geometry_changed(&view);
+                pointer_constraints::surface_committed(&view.surface());
+            } else {
+                self.damage_popup_parent(views);
             }
         }).unwrap();
     }
@@ -104,28 +175,48 @@ impl XdgV6ShellHandler for XdgV6 {
                    compositor: CompositorHandle,
                    _: SurfaceHandle,
                    shell_surface_handle: XdgV6ShellSurfaceHandle) {
-        let is_toplevel = with_handles!([(shell_surface: {&shell_surface_handle})] => {
+        // Requests to be maximized or fullscreen from before it was mapped.
+        let pending_state = with_handles!([(shell_surface: {&shell_surface_handle})] => {
             match shell_surface.state().unwrap() {
-                TopLevel(_) => true,
-                _ => false
+                TopLevel(toplevel) => {
+                    let pending = toplevel.client_pending_state();
+                    Some((pending.maximized, pending.fullscreen))
+                }
+                _ => None
             }
         }).unwrap();
+        let is_toplevel = pending_state.is_some();
+        let mapped_shell: ::Shell = shell_surface_handle.clone().into();
         dehandle!(
-            @compositor = {compositor};
+            @compositor = {compositor.clone()};
             let server: &mut ::Server = compositor.into();
             let ::Server { ref mut seat,
                          ref mut views,
+                         ref layer_surfaces,
                          ref cursor,
                          ref mut xcursor_manager,
                          .. } = *server;
             if is_toplevel {
-                let view = Rc::new(::View::new(::Shell::XdgV6(shell_surface_handle.into())));
+                let view = Rc::new(::View::new(mapped_shell.clone()));
                 views.push(view.clone());
+                damage::damage_view(&view);
+                window_management::view_mapped(&view);
                 seat.focus_view(view, views);
+            } else {
+                self.damage_popup_parent(views);
             };
             @cursor = {cursor};
-            seat.update_cursor_position(cursor, xcursor_manager, views, None)
+            seat.update_cursor_position(cursor, xcursor_manager, views, layer_surfaces, None)
         );
+        if let Some((maximized, fullscreen)) = pending_state {
+            with_handles!([(compositor: {compositor})] => {
+                let server: &mut ::Server = compositor.into();
+                let view = server.views.iter().find(|view| view.shell == mapped_shell).cloned();
+                if let Some(view) = view {
+                    server.set_initial_state(&view, maximized, fullscreen);
+                }
+            }).unwrap();
+        }
     }
 
     fn unmap_request(&mut self,
@@ -137,21 +228,22 @@ impl XdgV6ShellHandler for XdgV6 {
             let server: &mut ::Server = compositor.into();
             let ::Server { ref mut seat,
                          ref mut views,
+                         ref layer_surfaces,
                          ref cursor,
                          ref mut xcursor_manager,
                          .. } = *server;
             let destroyed_shell = shell_surface.into();
             if let Some(pos) = views.iter().position(|view| view.shell == destroyed_shell) {
-                views.remove(pos);
-            };
-
-            if views.len() > 0 {
-                seat.focus_view(views[0].clone(), views);
+                let view = views.remove(pos);
+                damage::damage_view(&view);
+                window_management::view_unmapped(&view);
             } else {
-                seat.clear_focus();
+                self.damage_popup_parent(views);
             };
+
+            seat.focus_next_view(views);
             @cursor = {cursor};
-            seat.update_cursor_position(cursor, xcursor_manager, views, None)
+            seat.update_cursor_position(cursor, xcursor_manager, views, layer_surfaces, None)
         );
     }
 }
@@ -160,9 +252,54 @@ pub struct XdgV6ShellManager;
 
 impl XdgV6ShellManagerHandler for XdgV6ShellManager {
     fn new_surface(&mut self,
-                   _: CompositorHandle,
-                   _: XdgV6ShellSurfaceHandle)
+                   compositor: CompositorHandle,
+                   shell_surface: XdgV6ShellSurfaceHandle)
                    -> (Option<Box<XdgV6ShellHandler>>, Option<Box<SurfaceHandler>>) {
-        (Some(Box::new(::XdgV6::new())), None)
+        let is_popup = with_handles!([(shell_surface: {&shell_surface})] => {
+            match shell_surface.state() {
+                Some(Popup(_)) => true,
+                _ => false
+            }
+        }).unwrap_or(false);
+        if !is_popup {
+            return (Some(Box::new(::XdgV6::new())), None)
+        }
+        let parent = with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            unconstrain_popup(server, &shell_surface)
+        }).unwrap_or(None);
+        match parent {
+            Some(parent) => (Some(Box::new(::XdgV6::new_popup(parent))), None),
+            None => (Some(Box::new(::XdgV6::new())), None)
+        }
     }
 }
+
+/// Moves a new popup so it stays inside the output of the view it belongs
+/// to, following the rules of its positioner, and returns that view's shell.
+///
+/// The parent of the popup is either the toplevel of a view or another popup
+/// of that view, so the view is the one that has the parent surface.
+fn unconstrain_popup(server: &mut ::Server,
+                     shell_surface: &XdgV6ShellSurfaceHandle)
+                     -> Option<::Shell> {
+    let parent = with_handles!([(shell_surface: {shell_surface})] => {
+        match shell_surface.state() {
+            Some(Popup(popup)) => Some(popup.parent()),
+            _ => None
+        }
+    }).ok()??;
+    let view = server.views.iter().find(|view| view.has_surface(&parent)).cloned()?;
+    let (output_area, _) = server.output_areas_of(&view)?;
+    // The box is relative to the toplevel the popup belongs to.
+    let origin = view.origin.get();
+    let constraint = Area::new(Origin::new(output_area.origin.x - origin.x,
+                                           output_area.origin.y - origin.y),
+                               output_area.size);
+    with_handles!([(shell_surface: {shell_surface})] => {
+        if let Some(Popup(popup)) = shell_surface.state() {
+            popup.unconstrain_from_box(constraint);
+        }
+    }).ok()?;
+    Some(view.shell.clone())
+}
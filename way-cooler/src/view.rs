@@ -1,6 +1,9 @@
+use output::damage;
+use protocols::{window_management, xdg_decoration};
 use std::cell::Cell;
-use wlroots::XdgV6ShellState::*;
-use wlroots::{Area, Origin, Size, SurfaceHandle};
+use std::cmp::{max, min};
+use std::rc::Rc;
+use wlroots::{Area, Origin, Size, SurfaceHandle, XdgShellState, XdgV6ShellState};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct PendingMoveResize {
@@ -14,14 +17,42 @@ pub struct PendingMoveResize {
 pub struct View {
     pub shell: ::Shell,
     pub origin: Cell<Origin>,
-    pub pending_move_resize: Cell<Option<PendingMoveResize>>
+    pub pending_move_resize: Cell<Option<PendingMoveResize>>,
+    /// The area the view and its popups covered when it was last damaged.
+    pub extents: Cell<Area>,
+    pub maximized: Cell<bool>,
+    pub fullscreen: Cell<bool>,
+    /// Minimized views are neither drawn nor can they get input.
+    pub minimized: Cell<bool>,
+    /// Where the view was before it was maximized or made fullscreen.
+    pub saved_geometry: Cell<Option<Area>>
+}
+
+/// Splits off the top view if it is fullscreen, as it is stacked above the
+/// panels of the top layer instead of below them.
+///
+/// Override redirect views, like the menus and tooltips of X11 clients, are
+/// kept in front of the focused view, so the top view is the first one that
+/// isn't override redirect. The ones in front of it stay above it.
+pub fn split_fullscreen(views: &mut [Rc<View>]) -> (&mut [Rc<View>], &mut [Rc<View>]) {
+    let top = views.iter().position(|view| !view.is_override_redirect());
+    let split = match top {
+        Some(index) if views[index].fullscreen.get() && !views[index].minimized.get() => index + 1,
+        _ => 0
+    };
+    views.split_at_mut(split)
 }
 
 impl View {
     pub fn new(shell: ::Shell) -> View {
         View { shell: shell,
                origin: Cell::new(Origin::default()),
-               pending_move_resize: Cell::new(None) }
+               pending_move_resize: Cell::new(None),
+               extents: Cell::new(Area::default()),
+               maximized: Cell::new(false),
+               fullscreen: Cell::new(false),
+               minimized: Cell::new(false),
+               saved_geometry: Cell::new(None) }
     }
 
     pub fn surface(&self) -> SurfaceHandle {
@@ -31,6 +62,43 @@ impl View {
                     xdg_surface.surface()
                 }).unwrap()
             }
+            ::Shell::Xdg(ref xdg_surface) => {
+                with_handles!([(xdg_surface: {xdg_surface})] => {
+                    xdg_surface.surface()
+                }).unwrap()
+            }
+            ::Shell::XWayland(ref xwayland_surface) => {
+                with_handles!([(xwayland_surface: {xwayland_surface})] => {
+                    xwayland_surface.surface()
+                }).unwrap().expect("XWayland view has no surface")
+            }
+        }
+    }
+
+    /// Whether the view should ever get the keyboard focus.
+    ///
+    /// Override redirect X11 windows, like menus and tooltips, never should.
+    pub fn accepts_focus(&self) -> bool {
+        match self.shell {
+            ::Shell::XWayland(ref xwayland_surface) => {
+                with_handles!([(xwayland_surface: {xwayland_surface})] => {
+                    !xwayland_surface.override_redirect()
+                }).unwrap_or(false)
+            }
+            _ => true
+        }
+    }
+
+    /// Whether the view is an override redirect X11 window, like a menu or a
+    /// tooltip. Those are stacked above all the other views.
+    pub fn is_override_redirect(&self) -> bool {
+        match self.shell {
+            ::Shell::XWayland(ref xwayland_surface) => {
+                with_handles!([(xwayland_surface: {xwayland_surface})] => {
+                    xwayland_surface.override_redirect()
+                }).unwrap_or(false)
+            }
+            _ => false
         }
     }
 
@@ -39,14 +107,31 @@ impl View {
             ::Shell::XdgV6(ref xdg_surface) => {
                 dehandle! (
                     @xdg_surface = {xdg_surface};
-                    match xdg_surface.state() {
-                        Some(&mut TopLevel(ref mut toplevel)) => {
-                            toplevel.set_activated(activate);
-                        },
-                        _ => unimplemented!()
+                    // Only toplevels are views, popups are never activated.
+                    if let Some(&mut XdgV6ShellState::TopLevel(ref mut toplevel)) =
+                        xdg_surface.state()
+                    {
+                        toplevel.set_activated(activate);
+                    }
+                );
+            }
+            ::Shell::Xdg(ref xdg_surface) => {
+                dehandle! (
+                    @xdg_surface = {xdg_surface};
+                    // Only toplevels are views, popups are never activated.
+                    if let Some(&mut XdgShellState::TopLevel(ref mut toplevel)) =
+                        xdg_surface.state()
+                    {
+                        toplevel.set_activated(activate);
                     }
                 );
             }
+            ::Shell::XWayland(ref xwayland_surface) => {
+                dehandle!(
+                    @xwayland_surface = {xwayland_surface};
+                    xwayland_surface.activate(activate)
+                );
+            }
         }
     }
 
@@ -58,39 +143,234 @@ impl View {
                     size
                 }).unwrap()
             }
+            ::Shell::Xdg(ref xdg_surface) => {
+                with_handles!([(xdg_surface: {xdg_surface})] => {
+                    let Area { origin: _, size } = xdg_surface.geometry();
+                    size
+                }).unwrap()
+            }
+            ::Shell::XWayland(ref xwayland_surface) => {
+                with_handles!([(xwayland_surface: {xwayland_surface})] => {
+                    let (width, height) = xwayland_surface.dimensions();
+                    Size::new(width as i32, height as i32)
+                }).unwrap()
+            }
+        }
+    }
+
+    /// The smallest and the largest size the client accepts.
+    ///
+    /// A dimension of 0 means that there is no limit.
+    pub fn size_constraints(&self) -> (Size, Size) {
+        match self.shell {
+            ::Shell::XdgV6(ref xdg_surface) => {
+                with_handles!([(xdg_surface: {xdg_surface})] => {
+                    match xdg_surface.state() {
+                        Some(&mut XdgV6ShellState::TopLevel(ref mut toplevel)) => {
+                            let state = toplevel.current_state();
+                            (Size::new(state.min_width as i32, state.min_height as i32),
+                             Size::new(state.max_width as i32, state.max_height as i32))
+                        }
+                        _ => (Size::default(), Size::default())
+                    }
+                }).unwrap()
+            }
+            ::Shell::Xdg(ref xdg_surface) => {
+                with_handles!([(xdg_surface: {xdg_surface})] => {
+                    match xdg_surface.state() {
+                        Some(&mut XdgShellState::TopLevel(ref mut toplevel)) => {
+                            let state = toplevel.current_state();
+                            (Size::new(state.min_width as i32, state.min_height as i32),
+                             Size::new(state.max_width as i32, state.max_height as i32))
+                        }
+                        _ => (Size::default(), Size::default())
+                    }
+                }).unwrap()
+            }
+            ::Shell::XWayland(ref xwayland_surface) => {
+                with_handles!([(xwayland_surface: {xwayland_surface})] => {
+                    match xwayland_surface.size_hints() {
+                        // X11 uses negative values for unset hints.
+                        Some(hints) => {
+                            (Size::new(max(hints.min_width, 0), max(hints.min_height, 0)),
+                             Size::new(max(hints.max_width, 0), max(hints.max_height, 0)))
+                        }
+                        None => (Size::default(), Size::default())
+                    }
+                }).unwrap()
+            }
+        }
+    }
+
+    /// How wide the border the compositor draws around the view is, 0 if
+    /// the client draws its own decorations.
+    pub fn border_width(&self) -> i32 {
+        if self.fullscreen.get() {
+            return 0
+        }
+        xdg_decoration::border_width(self)
+    }
+
+    /// Maximizes the view to the usable area of its output, or puts it back
+    /// where it was.
+    pub fn set_maximized(&self, maximized: bool, output_area: Area, usable_area: Area) {
+        self.maximized.set(maximized);
+        self.send_state();
+        self.apply_state(output_area, usable_area);
+    }
+
+    /// Makes the view cover its whole output, or puts it back where it was.
+    pub fn set_fullscreen(&self, fullscreen: bool, output_area: Area, usable_area: Area) {
+        self.fullscreen.set(fullscreen);
+        self.send_state();
+        // The border comes and goes.
+        damage::damage_view(self);
+        self.apply_state(output_area, usable_area);
+    }
+
+    /// Tells the client whether it is maximized and fullscreen.
+    fn send_state(&self) {
+        let (maximized, fullscreen) = (self.maximized.get(), self.fullscreen.get());
+        match self.shell {
+            ::Shell::XdgV6(ref xdg_surface) => {
+                with_handles!([(xdg_surface: {xdg_surface})] => {
+                    if let Some(&mut XdgV6ShellState::TopLevel(ref mut toplevel)) =
+                        xdg_surface.state()
+                    {
+                        toplevel.set_maximized(maximized);
+                        toplevel.set_fullscreen(fullscreen);
+                    }
+                }).unwrap();
+            }
+            ::Shell::Xdg(ref xdg_surface) => {
+                with_handles!([(xdg_surface: {xdg_surface})] => {
+                    if let Some(&mut XdgShellState::TopLevel(ref mut toplevel)) =
+                        xdg_surface.state()
+                    {
+                        toplevel.set_maximized(maximized);
+                        toplevel.set_fullscreen(fullscreen);
+                    }
+                }).unwrap();
+            }
+            ::Shell::XWayland(ref xwayland_surface) => {
+                with_handles!([(xwayland_surface: {xwayland_surface})] => {
+                    xwayland_surface.set_maximized(maximized);
+                    xwayland_surface.set_fullscreen(fullscreen);
+                }).unwrap();
+            }
+        }
+    }
+
+    /// Moves and resizes the view to match its state, saving its geometry
+    /// when it leaves the normal state and restoring it when it comes back.
+    pub fn apply_state(&self, output_area: Area, usable_area: Area) {
+        let area = if self.fullscreen.get() {
+            output_area
+        } else if self.maximized.get() {
+            // The border has to fit on the output as well.
+            let border_width = self.border_width();
+            let Area { origin, size } = usable_area;
+            Area::new(Origin::new(origin.x + border_width, origin.y + border_width),
+                      Size::new(size.width - border_width * 2, size.height - border_width * 2))
+        } else {
+            if let Some(saved_geometry) = self.saved_geometry.take() {
+                self.move_resize(saved_geometry);
+            }
+            return
+        };
+        if self.saved_geometry.get().is_none() {
+            self.saved_geometry.set(Some(Area::new(self.origin.get(), self.get_size())));
+        }
+        self.move_resize(area);
+    }
+
+    /// Sets the origin of the view, damaging where it was and where it is
+    /// now.
+    pub fn set_origin(&self, origin: Origin) {
+        if self.origin.get() == origin {
+            return
         }
+        self.origin.set(origin);
+        damage::damage_view(self);
     }
 
+    /// Moves the view without resizing it.
+    pub fn move_to(&self, origin: Origin) {
+        self.set_origin(origin);
+        if let ::Shell::XWayland(ref xwayland_surface) = self.shell {
+            // X11 clients need to know where they are to place their menus.
+            let Size { width, height } = self.get_size();
+            with_handles!([(xwayland_surface: {xwayland_surface})] => {
+                xwayland_surface.configure(origin.x as i16,
+                                           origin.y as i16,
+                                           width as u16,
+                                           height as u16);
+            }).unwrap();
+        }
+    }
+
+    /// Moves and resizes the view, within the size constraints of the
+    /// client.
+    ///
+    /// If the origin moves, the view is resized from its top or left edge.
+    /// The opposite edge then stays in place when the size is constrained.
     pub fn move_resize(&self, area: Area) {
-        let Area { origin: Origin { x, y },
+        let Area { origin: Origin { mut x, mut y },
                    size: Size { width, height } } = area;
-        let width = width as u32;
-        let height = height as u32;
 
         let Origin { x: view_x,
                      y: view_y } = self.origin.get();
 
         let update_x = x != view_x;
         let update_y = y != view_y;
+
+        let (min_size, max_size) = self.size_constraints();
+        let constrained_width = constrain(width, min_size.width, max_size.width);
+        let constrained_height = constrain(height, min_size.height, max_size.height);
+        if update_x {
+            x += width - constrained_width;
+        }
+        if update_y {
+            y += height - constrained_height;
+        }
+        let area = Area::new(Origin::new(x, y), Size::new(constrained_width, constrained_height));
+        let width = constrained_width as u32;
+        let height = constrained_height as u32;
         let mut serial = 0;
 
         match self.shell {
             ::Shell::XdgV6(ref xdg_surface) => {
                 with_handles!([(xdg_surface: {xdg_surface})] => {
-                    match xdg_surface.state() {
-                        Some(&mut TopLevel(ref mut toplevel)) => {
-                            // TODO apply size constraints
-                            serial = toplevel.set_size(width, height);
-                        },
-                        _ => unimplemented!()
+                    // Popups are positioned by their parent, not resized.
+                    if let Some(&mut XdgV6ShellState::TopLevel(ref mut toplevel)) =
+                        xdg_surface.state()
+                    {
+                        serial = toplevel.set_size(width, height);
+                    }
+                }).unwrap();
+            }
+            ::Shell::Xdg(ref xdg_surface) => {
+                with_handles!([(xdg_surface: {xdg_surface})] => {
+                    // Popups are positioned by their parent, not resized.
+                    if let Some(&mut XdgShellState::TopLevel(ref mut toplevel)) =
+                        xdg_surface.state()
+                    {
+                        serial = toplevel.set_size(width, height);
                     }
                 }).unwrap();
             }
+            ::Shell::XWayland(ref xwayland_surface) => {
+                // X11 clients don't acknowledge configures, they just commit.
+                with_handles!([(xwayland_surface: {xwayland_surface})] => {
+                    xwayland_surface.configure(x as i16, y as i16, width as u16, height as u16);
+                }).unwrap();
+            }
         }
 
         if serial == 0 {
             // size didn't change
-            self.origin.set(Origin { x, y });
+            self.set_origin(Origin { x, y });
+            window_management::geometry_changed(self);
         } else {
             self.pending_move_resize.set(Some(PendingMoveResize { update_x,
                                               update_y,
@@ -99,6 +379,36 @@ impl View {
         }
     }
 
+    /// Asks the client to close the view.
+    pub fn close(&self) {
+        match self.shell {
+            ::Shell::XdgV6(ref xdg_surface) => {
+                dehandle!(
+                    @xdg_surface = {xdg_surface};
+                    match xdg_surface.state() {
+                        Some(&mut XdgV6ShellState::TopLevel(ref mut toplevel)) => toplevel.close(),
+                        _ => {}
+                    }
+                );
+            }
+            ::Shell::Xdg(ref xdg_surface) => {
+                dehandle!(
+                    @xdg_surface = {xdg_surface};
+                    match xdg_surface.state() {
+                        Some(&mut XdgShellState::TopLevel(ref mut toplevel)) => toplevel.close(),
+                        _ => {}
+                    }
+                );
+            }
+            ::Shell::XWayland(ref xwayland_surface) => {
+                dehandle!(
+                    @xwayland_surface = {xwayland_surface};
+                    xwayland_surface.close()
+                );
+            }
+        }
+    }
+
     pub fn for_each_surface(&self, f: &mut FnMut(SurfaceHandle, i32, i32)) {
         match self.shell {
             ::Shell::XdgV6(ref xdg_surface) => {
@@ -106,6 +416,61 @@ impl View {
                     xdg_surface.for_each_surface(f);
                 }).unwrap();
             }
+            ::Shell::Xdg(ref xdg_surface) => {
+                with_handles!([(xdg_surface: {xdg_surface})] => {
+                    xdg_surface.for_each_surface(f);
+                }).unwrap();
+            }
+            ::Shell::XWayland(_) => f(self.surface(), 0, 0)
         }
     }
+
+    /// Whether the surface is the surface of the view or of one of its
+    /// popups.
+    pub fn has_surface(&self, surface: &SurfaceHandle) -> bool {
+        let mut found = false;
+        self.for_each_surface(&mut |other: SurfaceHandle, _, _| found |= other == *surface);
+        found
+    }
+}
+
+/// Keeps a length within the limits of a size constraint, and never below 1.
+fn constrain(length: i32, min_length: i32, max_length: i32) -> i32 {
+    let length = if max_length > 0 { min(length, max_length) } else { length };
+    max(length, max(min_length, 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn constrain_within_limits() {
+        assert_eq!(constrain(300, 100, 500), 300);
+        assert_eq!(constrain(50, 100, 500), 100);
+        assert_eq!(constrain(800, 100, 500), 500);
+    }
+
+    #[test]
+    fn constrain_unbounded() {
+        // A limit of 0 means there is none.
+        assert_eq!(constrain(5000, 0, 0), 5000);
+        assert_eq!(constrain(5000, 100, 0), 5000);
+        assert_eq!(constrain(50, 0, 500), 50);
+    }
+
+    #[test]
+    fn constrain_min_above_max() {
+        // The client asked for something impossible, the minimum wins.
+        assert_eq!(constrain(300, 400, 200), 400);
+        assert_eq!(constrain(100, 400, 200), 400);
+    }
+
+    #[test]
+    fn constrain_negative() {
+        // Dragging an edge past the opposite one gives negative lengths.
+        assert_eq!(constrain(-40, 0, 0), 1);
+        assert_eq!(constrain(0, 0, 500), 1);
+        assert_eq!(constrain(-40, 100, 500), 100);
+    }
 }
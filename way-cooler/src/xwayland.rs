@@ -1,8 +1,13 @@
 //! This handles the XWayland server and any XWayland clients that connect to
 //! Way Cooler.
 
-use wlroots::{CompositorHandle, SurfaceHandler, XWaylandManagerHandler, XWaylandSurfaceHandle,
-              XWaylandSurfaceHandler};
+use std::rc::Rc;
+
+use output::damage;
+use protocols::{pointer_constraints, window_management};
+use wlroots::xwayland::surface_events::{ConfigureEvent, MoveEvent, ResizeEvent};
+use wlroots::{CompositorHandle, Origin, SurfaceHandle, SurfaceHandler, XWaylandManagerHandler,
+              XWaylandSurfaceHandle, XWaylandSurfaceHandler};
 
 pub struct XWaylandManager;
 
@@ -15,11 +20,209 @@ impl XWaylandManager {
 impl XWaylandManagerHandler for XWaylandManager {
     fn on_ready(&mut self, _: CompositorHandle) {}
 
-    // TODO
     fn new_surface(&mut self,
                    _: CompositorHandle,
                    _: XWaylandSurfaceHandle)
                    -> (Option<Box<XWaylandSurfaceHandler>>, Option<Box<SurfaceHandler>>) {
-        (None, None)
+        (Some(Box::new(XWayland::new())), Some(Box::new(XWaylandSurface)))
+    }
+}
+
+/// Tracks commits of the surfaces of X11 windows, which unlike the other
+/// shells aren't reported through the shell handler.
+pub struct XWaylandSurface;
+
+impl SurfaceHandler for XWaylandSurface {
+    fn on_commit(&mut self, compositor: CompositorHandle, surface: SurfaceHandle) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let committed = server.views.iter().find(|view| {
+                match view.shell {
+                    ::Shell::XWayland(_) => view.surface() == surface,
+                    _ => false
+                }
+            });
+            if let Some(view) = committed {
+                damage::damage_view(view);
+                window_management::geometry_changed(view);
+                pointer_constraints::surface_committed(&surface);
+            }
+        }).unwrap();
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct XWayland;
+
+impl XWayland {
+    pub fn new() -> Self {
+        XWayland
+    }
+}
+
+impl XWayland {
+    /// Whether the window asked, through `_NET_WM_STATE`, to be maximized
+    /// and to be fullscreen.
+    fn requested_state(shell_surface: &XWaylandSurfaceHandle) -> (bool, bool) {
+        with_handles!([(shell_surface: {shell_surface})] => {
+            (shell_surface.maximized_vert() && shell_surface.maximized_horz(),
+             shell_surface.fullscreen())
+        }).unwrap_or((false, false))
+    }
+}
+
+impl XWaylandSurfaceHandler for XWayland {
+    fn on_configure(&mut self,
+                    compositor: CompositorHandle,
+                    _: Option<SurfaceHandle>,
+                    shell_surface: XWaylandSurfaceHandle,
+                    event: &ConfigureEvent) {
+        // We don't tile yet, so let the client have whatever it asks for.
+        with_handles!([(shell_surface: {&shell_surface})] => {
+            shell_surface.configure(event.x(), event.y(), event.width(), event.height());
+        }).unwrap();
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let configured = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == configured) {
+                view.set_origin(Origin::new(event.x() as i32, event.y() as i32));
+                window_management::geometry_changed(view);
+            }
+        }).unwrap();
+    }
+
+    fn on_move(&mut self,
+               compositor: CompositorHandle,
+               _: Option<SurfaceHandle>,
+               shell_surface: XWaylandSurfaceHandle,
+               _: &MoveEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref mut seat,
+                           ref mut cursor,
+                           .. } = *server;
+            let moving_shell: ::Shell = shell_surface.into();
+            let is_focused = seat.focused
+                                 .as_ref()
+                                 .map(|view| view.shell == moving_shell)
+                                 .unwrap_or(false);
+            if is_focused {
+                let view = seat.focused.clone().unwrap();
+                with_handles!([(cursor: {cursor})] => {
+                    seat.move_view(cursor, &view, None);
+                }).unwrap();
+            }
+        }).unwrap();
+    }
+
+    fn on_resize(&mut self,
+                 compositor: CompositorHandle,
+                 _: Option<SurfaceHandle>,
+                 shell_surface: XWaylandSurfaceHandle,
+                 event: &ResizeEvent) {
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref mut seat,
+                           ref mut views,
+                           ref mut cursor,
+                           .. } = *server;
+            let resizing_shell = shell_surface.into();
+
+            if let Some(view) = views.iter().find(|view| view.shell == resizing_shell).cloned() {
+                seat.begin_resize(cursor, view.clone(), views, event.edges())
+            }
+        }).unwrap();
+    }
+
+    fn on_maximize(&mut self,
+                   compositor: CompositorHandle,
+                   _: Option<SurfaceHandle>,
+                   shell_surface: XWaylandSurfaceHandle) {
+        let (maximized, _) = XWayland::requested_state(&shell_surface);
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let shell = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == shell).cloned() {
+                server.set_maximized(&view, maximized);
+            }
+        }).unwrap();
+    }
+
+    fn on_fullscreen(&mut self,
+                     compositor: CompositorHandle,
+                     _: Option<SurfaceHandle>,
+                     shell_surface: XWaylandSurfaceHandle) {
+        let (_, fullscreen) = XWayland::requested_state(&shell_surface);
+        with_handles!([(compositor: {compositor})] => {
+            let server: &mut ::Server = compositor.into();
+            let shell = shell_surface.into();
+            if let Some(view) = server.views.iter().find(|view| view.shell == shell).cloned() {
+                server.set_fullscreen(&view, fullscreen, None);
+            }
+        }).unwrap();
+    }
+
+    fn on_map(&mut self, compositor: CompositorHandle, shell_surface: XWaylandSurfaceHandle) {
+        let (origin, override_redirect) = with_handles!([(shell_surface: {&shell_surface})] => {
+            let (x, y) = shell_surface.coords();
+            (Origin::new(x as i32, y as i32), shell_surface.override_redirect())
+        }).unwrap();
+        let (maximized, fullscreen) = XWayland::requested_state(&shell_surface);
+        let mapped_shell = ::Shell::XWayland(shell_surface.clone());
+        dehandle!(
+            @compositor = {compositor.clone()};
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref mut seat,
+                         ref mut views,
+                         ref layer_surfaces,
+                         ref cursor,
+                         ref mut xcursor_manager,
+                         .. } = *server;
+            let view = Rc::new(::View::new(mapped_shell.clone()));
+            view.origin.set(origin);
+            damage::damage_view(&view);
+            // Menus and tooltips are placed by the client, stack above
+            // everything and are not something the window manager handles.
+            views.insert(0, view.clone());
+            if !override_redirect {
+                window_management::view_mapped(&view);
+                seat.focus_view(view, views);
+            };
+            @cursor = {cursor};
+            seat.update_cursor_position(cursor, xcursor_manager, views, layer_surfaces, None)
+        );
+        if !override_redirect {
+            with_handles!([(compositor: {compositor})] => {
+                let server: &mut ::Server = compositor.into();
+                let view = server.views.iter().find(|view| view.shell == mapped_shell).cloned();
+                if let Some(view) = view {
+                    server.set_initial_state(&view, maximized, fullscreen);
+                }
+            }).unwrap();
+        }
+    }
+
+    fn on_unmap(&mut self, compositor: CompositorHandle, shell_surface: XWaylandSurfaceHandle) {
+        dehandle!(
+            @compositor = {compositor};
+            let server: &mut ::Server = compositor.into();
+            let ::Server { ref mut seat,
+                         ref mut views,
+                         ref layer_surfaces,
+                         ref cursor,
+                         ref mut xcursor_manager,
+                         .. } = *server;
+            let destroyed_shell = shell_surface.into();
+            if let Some(pos) = views.iter().position(|view| view.shell == destroyed_shell) {
+                let view = views.remove(pos);
+                damage::damage_view(&view);
+                window_management::view_unmapped(&view);
+                if seat.focused.as_ref() == Some(&view) {
+                    seat.focus_next_view(views);
+                }
+            };
+            @cursor = {cursor};
+            seat.update_cursor_position(cursor, xcursor_manager, views, layer_surfaces, None)
+        );
     }
 }